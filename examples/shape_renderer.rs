@@ -1,4 +1,4 @@
-use moxui::shape_renderer::{ShapeInstance, ShapeRenderer};
+use moxui::shape_renderer::{GradientStop, Paint, Shape, ShapeInstance, ShapeRenderer};
 use moxui::viewport::{Resolution, Viewport};
 use std::sync::Arc;
 use winit::application::ApplicationHandler;
@@ -222,7 +222,7 @@ impl<'window> WgpuCtx<'window> {
             timestamp_writes: None,
         });
 
-        let shapes = vec![
+        let mut shapes: Vec<Shape> = [
             ShapeInstance {
                 rect_pos: [0., 0.],
                 rect_size: [400., 300.],
@@ -232,6 +232,7 @@ impl<'window> WgpuCtx<'window> {
                 border_size: [1., 1., 1., 1.],
                 border_color: [1., 0., 1., 1.],
                 depth: 0.,
+                ..Default::default()
             },
             ShapeInstance {
                 rect_pos: [450., 0.],
@@ -242,6 +243,10 @@ impl<'window> WgpuCtx<'window> {
                 border_size: [1., 1., 1., 1.],
                 border_color: [1., 0., 1., 1.],
                 depth: 0.1,
+                shadow_offset: [0., 8.],
+                shadow_blur: 24.,
+                shadow_color: [0., 0., 0., 0.6],
+                ..Default::default()
             },
             ShapeInstance {
                 rect_pos: [550., 50.],
@@ -252,6 +257,7 @@ impl<'window> WgpuCtx<'window> {
                 border_size: [1., 1., 1., 1.],
                 border_color: [1., 0., 1., 1.],
                 depth: 0.,
+                ..Default::default()
             },
             ShapeInstance {
                 rect_pos: [50., 400.],
@@ -262,6 +268,7 @@ impl<'window> WgpuCtx<'window> {
                 border_size: [1., 1., 1., 1.],
                 border_color: [1., 0., 1., 1.],
                 depth: 0.,
+                ..Default::default()
             },
             ShapeInstance {
                 rect_pos: [300., 400.],
@@ -272,11 +279,31 @@ impl<'window> WgpuCtx<'window> {
                 border_size: [1., 1., 1., 1.],
                 border_color: [1., 0., 1., 1.],
                 depth: 0.,
+                ..Default::default()
             },
-        ];
+        ]
+        .into_iter()
+        .map(Shape::from)
+        .collect();
+
+        shapes[0].fill = Paint::Linear {
+            angle: 135.,
+            stops: vec![
+                GradientStop::new(0., [1., 1., 0., 1.]),
+                GradientStop::new(1., [0., 0.5, 1., 1.]),
+            ],
+        };
+        shapes[3].fill = Paint::Radial {
+            center: [0.5, 0.5],
+            radius: [0.5, 0.5],
+            stops: vec![
+                GradientStop::new(0., [0., 1., 1., 1.]),
+                GradientStop::new(1., [0., 0.2, 0.4, 1.]),
+            ],
+        };
 
         self.shape_renderer
-            .prepare(&self.device, &self.queue, &shapes);
+            .prepare_shapes(&self.device, &self.queue, &shapes);
         self.shape_renderer.render(&mut render_pass, &self.viewport);
 
         drop(render_pass);
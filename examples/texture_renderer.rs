@@ -1,5 +1,8 @@
 use moxui::{
-    texture_renderer::{Buffer, TextureArea, TextureBounds, TextureRenderer},
+    clip::ClipId,
+    texture_renderer::{
+        Buffer, TextureArea, TextureBounds, TextureId, TextureRenderer, Transforms,
+    },
     viewport::{Resolution, Viewport},
 };
 use std::sync::Arc;
@@ -120,6 +123,7 @@ pub struct WgpuCtx<'window> {
     queue: wgpu::Queue,
     viewport: Viewport,
     texture_renderer: Option<TextureRenderer>,
+    checkerboard: Option<TextureId>,
 }
 
 impl<'window> WgpuCtx<'window> {
@@ -152,6 +156,7 @@ impl<'window> WgpuCtx<'window> {
             device,
             queue,
             texture_renderer: None,
+            checkerboard: None,
         }
     }
 
@@ -169,68 +174,81 @@ impl<'window> WgpuCtx<'window> {
 
         let width = 400;
         let height = 300;
-        let mut bytes = vec![0u8; width * height * 4];
-
-        for y in 0..height {
-            for x in 0..width {
-                let i = (y * width + x) * 4;
-                let checker = ((x / 40) + (y / 40)) % 2;
-                if checker == 0 {
-                    bytes[i] = 255;
-                    bytes[i + 1] = 0;
-                    bytes[i + 2] = 0;
-                    bytes[i + 3] = 255;
-                } else {
-                    bytes[i] = 0;
-                    bytes[i + 1] = 0;
-                    bytes[i + 2] = 255;
-                    bytes[i + 3] = 255;
-                }
+
+        let texture_renderer = self.texture_renderer.get_or_insert_with(|| {
+            TextureRenderer::new(
+                &self.device,
+                self.surface_config.format,
+                width.max(height),
+                self.surface_config.width,
+                self.surface_config.height,
+            )
+        });
+
+        // The checkerboard only needs to be uploaded once, later frames draw it by handle
+        let checkerboard = match self.checkerboard {
+            Some(id) if texture_renderer.contains(id) => id,
+            _ => {
+                let id = texture_renderer
+                    .upload(&self.queue, width, height, &checkerboard(width, height))
+                    .expect("Failed to upload texture");
+                self.checkerboard = Some(id);
+                id
             }
-        }
+        };
 
         let mut buffer = Buffer::new(width as f32, height as f32);
-        buffer.set_bytes(&bytes);
-
-        let left = 0.0;
-        let top = 0.0;
+        buffer.set_texture(checkerboard);
+        buffer.set_shadow(8.0, 8.0, 16.0, 0.0);
+        buffer.set_shadow_color(0.0, 0.0, 0.0, 0.5);
 
         let texture = TextureArea {
-            left,
-            top,
-            scale: 1.0,
+            left: 50.0,
+            top: 50.0,
+            transforms: Transforms {
+                rotate: 5.0,
+                ..Default::default()
+            },
             bounds: TextureBounds {
                 left: 0,
                 top: 0,
-                right: width as u32,
-                bottom: height as u32,
+                right: self.surface_config.width,
+                bottom: self.surface_config.height,
             },
             buffer,
             radius: [0., 0., 0., 0.],
-            rotation: 0.,
-            skew: [0., 0.],
             depth: 0.,
+            clip: ClipId::default(),
         };
 
-        let max_icon_size = width.max(height) as u32;
-        if self.texture_renderer.is_none() {
-            let mut texture_renderer = TextureRenderer::new(
-                &self.device,
-                self.surface_config.format,
-                max_icon_size,
-                self.surface_config.width,
-                self.surface_config.height,
-            );
-            texture_renderer.prepare(&self.device, &self.queue, &[texture]);
-            texture_renderer.render(&texture_view, &mut encoder, &self.viewport);
-            self.texture_renderer = Some(texture_renderer);
-        } else {
-            let texture_renderer = self.texture_renderer.as_mut().unwrap();
-            texture_renderer.prepare(&self.device, &self.queue, &[texture]);
-            texture_renderer.render(&texture_view, &mut encoder, &self.viewport);
-        }
+        texture_renderer.prepare(&self.device, &self.queue, &[texture]);
+        texture_renderer.render(&texture_view, &mut encoder, &self.viewport);
 
         self.queue.submit(Some(encoder.finish()));
         surface_texture.present();
     }
 }
+
+fn checkerboard(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = vec![0u8; (width * height * 4) as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = ((y * width + x) * 4) as usize;
+            let checker = ((x / 40) + (y / 40)) % 2;
+            if checker == 0 {
+                bytes[i] = 255;
+                bytes[i + 1] = 0;
+                bytes[i + 2] = 0;
+                bytes[i + 3] = 255;
+            } else {
+                bytes[i] = 0;
+                bytes[i + 1] = 0;
+                bytes[i + 2] = 255;
+                bytes[i + 3] = 255;
+            }
+        }
+    }
+
+    bytes
+}
@@ -66,4 +66,12 @@ where
     }
 }
 
+impl<T> InstanceBuffer<T> {
+    /// Size of the buffer in bytes, which can be larger than the instances
+    /// last written to it.
+    pub fn capacity(&self) -> u64 {
+        self.buffer.size()
+    }
+}
+
 pub trait Instance: super::DataDescription {}
@@ -1,6 +1,5 @@
 pub mod instance;
 
-use std::rc::Rc;
 use wgpu::util::DeviceExt;
 
 pub trait DataDescription {
@@ -163,10 +162,12 @@ pub struct StorageBuffer<T>
 where
     T: Clone,
 {
-    _data: Rc<[T]>,
+    /// How many elements fit into the buffer.
+    capacity: usize,
     pub buffer: wgpu::Buffer,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    _data: std::marker::PhantomData<T>,
 }
 
 impl<T> StorageBuffer<T>
@@ -196,6 +197,44 @@ where
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
+        Self::from_buffer(device, buffer, data.len())
+    }
+
+    /// Creates an uninitialized buffer with room for `capacity` elements,
+    /// to be filled with [`write`](Self::write).
+    pub fn with_capacity(device: &wgpu::Device, capacity: usize) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Storage Buffer"),
+            size: (std::mem::size_of::<T>() * capacity.max(1)) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self::from_buffer(device, buffer, capacity.max(1))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Writes `data` to the start of the buffer, which must have room for
+    /// it.
+    pub fn write(&self, queue: &wgpu::Queue, data: &[T]) {
+        self.write_at(queue, 0, data);
+    }
+
+    /// Writes `data` starting at element `index`, e.g. to append to what
+    /// was written before.
+    pub fn write_at(&self, queue: &wgpu::Queue, index: usize, data: &[T]) {
+        debug_assert!(index + data.len() <= self.capacity);
+
+        let offset = (index * std::mem::size_of::<T>()) as u64;
+        queue.write_buffer(&self.buffer, offset, unsafe {
+            std::slice::from_raw_parts(data as *const [T] as *const u8, std::mem::size_of_val(data))
+        });
+    }
+
+    fn from_buffer(device: &wgpu::Device, buffer: wgpu::Buffer, capacity: usize) -> Self {
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Storage Buffer Bind Group Layout"),
             entries: &[wgpu::BindGroupLayoutEntry {
@@ -220,10 +259,11 @@ where
         });
 
         Self {
-            _data: data.into(),
+            capacity,
             buffer,
             bind_group_layout,
             bind_group,
+            _data: std::marker::PhantomData,
         }
     }
 }
@@ -0,0 +1,32 @@
+// Appended to the shaders of the renderers that clip, which bind the clips
+// of `ClipStack` as group 2
+
+struct ClipRect {
+    bounds: vec4<f32>,  // [left, top, right, bottom]
+    radius: vec4<f32>,  // [top_left, top_right, bottom_right, bottom_left]
+};
+@group(2) @binding(0)
+var<storage, read> clip_rects: array<ClipRect>;
+
+fn clip_rect_distance(position: vec2<f32>, clip: ClipRect) -> f32 {
+    let half_size = (clip.bounds.zw - clip.bounds.xy) * 0.5;
+    let p = position - clip.bounds.xy - half_size;
+
+    let top = select(clip.radius.x, clip.radius.y, p.x > 0.0);
+    let bottom = select(clip.radius.w, clip.radius.z, p.x > 0.0);
+    let radius = clamp(select(top, bottom, p.y > 0.0), 0.0, max(min(half_size.x, half_size.y), 0.0));
+
+    let q = abs(p) - half_size + radius;
+    return min(max(q.x, q.y), 0.0) + length(max(q, vec2<f32>(0.0))) - radius;
+}
+
+// How much of the pixel at `position` the clips `[first, count]` let
+// through. Edges are antialiased over a pixel without derivatives, so that
+// this can be called from non-uniform control flow.
+fn clip_coverage(position: vec2<f32>, clip: vec2<u32>) -> f32 {
+    var coverage = 1.0;
+    for (var i = clip.x; i < clip.x + clip.y; i++) {
+        coverage *= clamp(0.5 - clip_rect_distance(position, clip_rects[i]), 0.0, 1.0);
+    }
+    return coverage;
+}
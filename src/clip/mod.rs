@@ -0,0 +1,285 @@
+/// A rectangle drawing is confined to, optionally with rounded corners.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Clip {
+    /// `[left, top, width, height]` in pixels of the target, i.e. after
+    /// scaling.
+    pub rect: [f32; 4],
+    /// Corner radii in pixels as `[top_left, top_right, bottom_right, bottom_left]`.
+    pub radius: [f32; 4],
+}
+
+impl Clip {
+    /// An axis aligned clip, like a scissor rect.
+    pub fn rect(left: f32, top: f32, width: f32, height: f32) -> Self {
+        Self {
+            rect: [left, top, width, height],
+            radius: [0.; 4],
+        }
+    }
+
+    /// A clip with rounded corners, e.g. of a card or a scroll view.
+    pub fn rounded(left: f32, top: f32, width: f32, height: f32, radius: [f32; 4]) -> Self {
+        Self {
+            rect: [left, top, width, height],
+            radius,
+        }
+    }
+}
+
+/// A clip as the shaders read it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ClipRect {
+    /// `[left, top, right, bottom]` in pixels.
+    bounds: [f32; 4],
+    radius: [f32; 4],
+}
+
+impl ClipRect {
+    fn is_rounded(&self) -> bool {
+        self.radius.iter().any(|r| *r > 0.)
+    }
+}
+
+impl From<Clip> for ClipRect {
+    fn from(clip: Clip) -> Self {
+        let [left, top, width, height] = clip.rect;
+        Self {
+            bounds: [left, top, left + width, top + height],
+            radius: clip.radius,
+        }
+    }
+}
+
+/// The clips in effect at some point of a [`ClipStack`], given to shapes,
+/// textures and backdrops. The default clips nothing.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClipId {
+    first: u32,
+    count: u32,
+}
+
+/// Nested clips, e.g. of a scrolling list inside a rounded container.
+///
+/// Every [`push`](Self::push) clips to the new clip on top of all clips
+/// already in effect, until the matching [`pop`](Self::pop). Axis aligned
+/// clips are merged into one, rounded ones are each tested on their own.
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::clip::{Clip, ClipStack};
+///
+/// let mut clips = ClipStack::new();
+/// let container = clips.push(Clip::rounded(0., 0., 200., 400., [12.; 4]));
+/// let list = clips.push(Clip::rect(0., 40., 200., 360.));
+///
+/// row.instance.clip = list;
+/// clips.pop();
+///
+/// shape_renderer.prepare_clips(&device, &queue, &clips);
+/// shape_renderer.prepare_shapes(&device, &queue, &[row]);
+/// ```
+#[derive(Debug, Default)]
+pub struct ClipStack {
+    /// Clips of every [`ClipId`], each in a range of their own.
+    rects: Vec<ClipRect>,
+    stack: Vec<ClipId>,
+}
+
+impl ClipStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Confines everything drawn with the returned id to `clip` and the
+    /// clips already in effect.
+    pub fn push(&mut self, clip: Clip) -> ClipId {
+        let current = self.current();
+        let first = self.rects.len();
+        self.rects
+            .extend_from_within(current.first as usize..(current.first + current.count) as usize);
+
+        let rect = ClipRect::from(clip);
+        let merged = (!rect.is_rounded())
+            .then(|| {
+                self.rects[first..]
+                    .iter_mut()
+                    .find(|existing| !existing.is_rounded())
+            })
+            .flatten();
+
+        match merged {
+            Some(existing) => {
+                existing.bounds = [
+                    existing.bounds[0].max(rect.bounds[0]),
+                    existing.bounds[1].max(rect.bounds[1]),
+                    existing.bounds[2].min(rect.bounds[2]),
+                    existing.bounds[3].min(rect.bounds[3]),
+                ];
+            }
+            None => self.rects.push(rect),
+        }
+
+        let id = ClipId {
+            first: first as u32,
+            count: (self.rects.len() - first) as u32,
+        };
+        self.stack.push(id);
+        id
+    }
+
+    /// Restores the clips in effect before the last [`push`](Self::push).
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    /// The clips in effect, or the default [`ClipId`] outside of any clip.
+    pub fn current(&self) -> ClipId {
+        self.stack.last().copied().unwrap_or_default()
+    }
+
+    /// Forgets all clips, which invalidates their ids. Call this before
+    /// building the clips of a new frame.
+    pub fn clear(&mut self) {
+        self.rects.clear();
+        self.stack.clear();
+    }
+
+    /// Axis aligned `[left, top, right, bottom]` that `clip` confines
+    /// drawing to, ignoring rounded corners, or `None` if it clips nothing
+    /// or isn't an id of this stack anymore.
+    pub fn bounds(&self, clip: ClipId) -> Option<[f32; 4]> {
+        self.rects
+            .get(clip.first as usize..(clip.first + clip.count) as usize)?
+            .iter()
+            .map(|rect| rect.bounds)
+            .reduce(|a, b| {
+                [
+                    a[0].max(b[0]),
+                    a[1].max(b[1]),
+                    a[2].min(b[2]),
+                    a[3].min(b[3]),
+                ]
+            })
+    }
+
+    /// Bounds of `clip` for [`TextArea::bounds`], which glyphon clips the
+    /// text to. Rounded corners are applied through [`TextArea::clip`].
+    ///
+    /// [`TextArea::bounds`]: crate::text_renderer::TextArea::bounds
+    /// [`TextArea::clip`]: crate::text_renderer::TextArea::clip
+    #[cfg(feature = "text_renderer")]
+    pub fn text_bounds(&self, clip: ClipId) -> glyphon::TextBounds {
+        match self.bounds(clip) {
+            Some([left, top, right, bottom]) => glyphon::TextBounds {
+                left: left.floor() as i32,
+                top: top.floor() as i32,
+                right: right.ceil() as i32,
+                bottom: bottom.ceil() as i32,
+            },
+            None => glyphon::TextBounds::default(),
+        }
+    }
+}
+
+/// Clip rects uploaded for a renderer, bound as `@group(2)` of its shaders,
+/// which append `clip.wgsl` to read them.
+#[cfg(any(
+    feature = "shape_renderer",
+    feature = "texture_renderer",
+    feature = "text_renderer"
+))]
+pub(crate) struct ClipBuffer {
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    buffer: crate::buffers::StorageBuffer<ClipRect>,
+}
+
+#[cfg(any(
+    feature = "shape_renderer",
+    feature = "texture_renderer",
+    feature = "text_renderer"
+))]
+impl ClipBuffer {
+    /// WGSL that shaders append to their own, providing `clip_coverage`.
+    pub const SHADER: &'static str = include_str!("clip.wgsl");
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("clip_bind_group_layout"),
+        });
+
+        let buffer = crate::buffers::StorageBuffer::with_capacity(device, 16);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &buffer);
+
+        Self {
+            bind_group_layout,
+            bind_group,
+            buffer,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Uploads the clips of `clips`, growing the buffer and replacing the
+    /// bind group when they don't fit.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        if self.buffer.capacity() < clips.rects.len() {
+            let capacity = clips.rects.len().max(self.buffer.capacity() * 2);
+            self.buffer = crate::buffers::StorageBuffer::with_capacity(device, capacity);
+            self.bind_group =
+                Self::create_bind_group(device, &self.bind_group_layout, &self.buffer);
+        }
+
+        self.buffer.write(queue, &clips.rects);
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &crate::buffers::StorageBuffer<ClipRect>,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.buffer.as_entire_binding(),
+            }],
+            label: Some("clip_bind_group"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_ids_clip_nothing() {
+        let mut clips = ClipStack::new();
+        clips.push(Clip::rect(0., 0., 100., 100.));
+        let nested = clips.push(Clip::rect(10., 10., 20., 20.));
+        assert_eq!(clips.bounds(nested), Some([10., 10., 30., 30.]));
+
+        clips.clear();
+        assert_eq!(clips.bounds(nested), None);
+    }
+}
@@ -0,0 +1,394 @@
+//! Resolves icon names like `firefox` or `dialog-warning` to files, following
+//! the freedesktop Icon Theme Specification.
+
+#[cfg(feature = "texture_renderer")]
+use crate::image::Image;
+use std::collections::HashMap;
+#[cfg(feature = "texture_renderer")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Theme every other theme falls back to.
+pub const FALLBACK_THEME: &str = "hicolor";
+
+/// Extensions of the icon files that can be loaded, in order of preference.
+#[cfg(feature = "svg")]
+const EXTENSIONS: &[&str] = &["png", "svg"];
+#[cfg(not(feature = "svg"))]
+const EXTENSIONS: &[&str] = &["png"];
+
+/// How the icons of a directory of a theme can be scaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// Directory of a theme holding icons of one size.
+#[derive(Debug, Clone)]
+struct Directory {
+    path: String,
+    size: u32,
+    scale: u32,
+    kind: Kind,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+}
+
+impl Directory {
+    fn matches(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+
+        match self.kind {
+            Kind::Fixed => self.size == size,
+            Kind::Scalable => (self.min_size..=self.max_size).contains(&size),
+            Kind::Threshold => (self.size.saturating_sub(self.threshold)
+                ..=self.size.saturating_add(self.threshold))
+                .contains(&size),
+        }
+    }
+
+    /// How far the icons are from `size` at `scale` in pixels, as defined
+    /// by the specification.
+    fn distance(&self, size: u32, scale: u32) -> u32 {
+        // Sizes of themes and callers can be anything
+        let pixels = size.saturating_mul(scale);
+        let scaled = |size: u32| size.saturating_mul(self.scale);
+
+        let (min, max) = match self.kind {
+            Kind::Fixed => (self.size, self.size),
+            Kind::Scalable => (self.min_size, self.max_size),
+            Kind::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size.saturating_add(self.threshold),
+            ),
+        };
+
+        if pixels < scaled(min) {
+            // Threshold directories measure against their min and max
+            // sizes, not the ends of their threshold
+            match self.kind {
+                Kind::Threshold => scaled(self.min_size).abs_diff(pixels),
+                _ => scaled(min) - pixels,
+            }
+        } else if pixels > scaled(max) {
+            match self.kind {
+                Kind::Threshold => pixels.abs_diff(scaled(self.max_size)),
+                _ => pixels - scaled(max),
+            }
+        } else {
+            0
+        }
+    }
+}
+
+/// A theme, parsed from its `index.theme`.
+#[derive(Debug)]
+struct Theme {
+    /// Directories of the theme in all base directories.
+    roots: Vec<PathBuf>,
+    inherits: Vec<String>,
+    directories: Vec<Directory>,
+}
+
+impl Theme {
+    /// Reads the theme `name` from the first base directory with an
+    /// `index.theme` for it.
+    fn load(base_dirs: &[PathBuf], name: &str) -> Option<Self> {
+        let roots = base_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .filter(|dir| dir.is_dir())
+            .collect::<Vec<_>>();
+
+        let index = roots
+            .iter()
+            .find_map(|root| std::fs::read_to_string(root.join("index.theme")).ok())?;
+
+        let mut theme = Self::parse(&index);
+        theme.roots = roots;
+        Some(theme)
+    }
+
+    fn parse(index: &str) -> Self {
+        let mut sections: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+        let mut section = None;
+
+        index.lines().map(str::trim).for_each(|line| {
+            if line.starts_with('#') {
+                return;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name);
+            } else if let (Some(section), Some((key, value))) = (section, line.split_once('=')) {
+                sections
+                    .entry(section)
+                    .or_default()
+                    .insert(key.trim(), value.trim());
+            }
+        });
+
+        let list = |value: Option<&&str>| {
+            value
+                .into_iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+
+        let theme = sections.get("Icon Theme");
+        let mut paths = list(theme.and_then(|theme| theme.get("Directories")));
+        paths.extend(list(theme.and_then(|theme| theme.get("ScaledDirectories"))));
+        paths.dedup();
+
+        let directories = paths
+            .into_iter()
+            .filter_map(|path| {
+                let keys = sections.get(path.as_str())?;
+                let number = |key: &str| keys.get(key).and_then(|value| value.parse::<u32>().ok());
+
+                let size = number("Size")?;
+                Some(Directory {
+                    size,
+                    scale: number("Scale").unwrap_or(1).max(1),
+                    kind: match keys.get("Type").copied() {
+                        Some("Fixed") => Kind::Fixed,
+                        Some("Scalable") => Kind::Scalable,
+                        _ => Kind::Threshold,
+                    },
+                    min_size: number("MinSize").unwrap_or(size),
+                    max_size: number("MaxSize").unwrap_or(size),
+                    threshold: number("Threshold").unwrap_or(2),
+                    path,
+                })
+            })
+            .collect();
+
+        Self {
+            roots: Vec::new(),
+            inherits: list(theme.and_then(|theme| theme.get("Inherits"))),
+            directories,
+        }
+    }
+
+    /// Icon `name` of this theme closest to `size` at `scale`, ignoring the
+    /// themes it inherits from.
+    fn lookup(&self, name: &str, size: u32, scale: u32) -> Option<PathBuf> {
+        if let Some(file) = self
+            .directories
+            .iter()
+            .filter(|directory| directory.matches(size, scale))
+            .find_map(|directory| self.file(directory, name))
+        {
+            return Some(file);
+        }
+
+        self.directories
+            .iter()
+            .filter_map(|directory| {
+                let file = self.file(directory, name)?;
+                Some((directory.distance(size, scale), file))
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, file)| file)
+    }
+
+    /// File of the icon `name` in `directory` of any of the theme's roots.
+    fn file(&self, directory: &Directory, name: &str) -> Option<PathBuf> {
+        self.roots.iter().find_map(|root| {
+            EXTENSIONS
+                .iter()
+                .map(|extension| {
+                    root.join(&directory.path)
+                        .join(format!("{name}.{extension}"))
+                })
+                .find(|file| file.is_file())
+        })
+    }
+}
+
+/// Finds icons by name in the installed icon themes.
+///
+/// Icons are looked up in the given theme, then in the themes it inherits
+/// from, then in [`FALLBACK_THEME`] and finally directly in the base
+/// directories, e.g. `/usr/share/pixmaps`. Parsed themes are kept for
+/// later lookups.
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::icon::IconLookup;
+///
+/// let mut icons = IconLookup::new();
+///
+/// // 32x32 logical pixels on an output with a scale of 2
+/// if let Some(icon) = icons.load("Adwaita", "dialog-warning", 32, 2)? {
+///     texture_renderer.upload(&queue, icon.width(), icon.height(), icon.data())?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct IconLookup {
+    base_dirs: Vec<PathBuf>,
+    themes: HashMap<String, Option<Arc<Theme>>>,
+}
+
+impl Default for IconLookup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IconLookup {
+    /// Looks icons up in the base directories of the specification:
+    /// `~/.icons`, `icons` in the XDG data directories and
+    /// `/usr/share/pixmaps`.
+    pub fn new() -> Self {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home.as_ref().map(|home| home.join(".local/share")));
+
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+
+        let base_dirs = home
+            .map(|home| home.join(".icons"))
+            .into_iter()
+            .chain(data_home.map(|dir| dir.join("icons")))
+            .chain(
+                data_dirs
+                    .split(':')
+                    .map(PathBuf::from)
+                    .filter(|dir| dir.is_absolute())
+                    .map(|dir| dir.join("icons")),
+            )
+            .chain([PathBuf::from("/usr/share/pixmaps")])
+            .collect();
+
+        Self::with_base_dirs(base_dirs)
+    }
+
+    /// Looks icons up in `base_dirs`, in order of preference.
+    pub fn with_base_dirs(base_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            base_dirs,
+            themes: HashMap::new(),
+        }
+    }
+
+    pub fn base_dirs(&self) -> &[PathBuf] {
+        &self.base_dirs
+    }
+
+    /// File of the icon `name` of `theme` closest to `size`x`size` logical
+    /// pixels at `scale`, or `None` if no theme has it.
+    pub fn find(&mut self, theme: &str, name: &str, size: u32, scale: u32) -> Option<PathBuf> {
+        let scale = scale.max(1);
+        let mut visited = Vec::new();
+
+        self.find_in(theme, name, size, scale, &mut visited)
+            .or_else(|| self.find_in(FALLBACK_THEME, name, size, scale, &mut visited))
+            .or_else(|| {
+                // Unthemed icons, e.g. in `/usr/share/pixmaps`
+                self.base_dirs.iter().find_map(|dir| {
+                    EXTENSIONS
+                        .iter()
+                        .map(|extension| dir.join(format!("{name}.{extension}")))
+                        .find(|file| file.is_file())
+                })
+            })
+    }
+
+    /// Looks `name` up in `theme` and the themes it inherits from, skipping
+    /// the themes already looked in.
+    fn find_in(
+        &mut self,
+        theme: &str,
+        name: &str,
+        size: u32,
+        scale: u32,
+        visited: &mut Vec<String>,
+    ) -> Option<PathBuf> {
+        if visited.iter().any(|visited| visited == theme) {
+            return None;
+        }
+        visited.push(theme.to_owned());
+
+        let theme = self.theme(theme)?;
+        theme.lookup(name, size, scale).or_else(|| {
+            theme
+                .inherits
+                .iter()
+                .find_map(|parent| self.find_in(parent, name, size, scale, visited))
+        })
+    }
+
+    fn theme(&mut self, name: &str) -> Option<Arc<Theme>> {
+        if let Some(theme) = self.themes.get(name) {
+            return theme.clone();
+        }
+
+        let theme = Theme::load(&self.base_dirs, name).map(Arc::new);
+        self.themes.insert(name.to_owned(), theme.clone());
+        theme
+    }
+
+    /// Forgets the parsed themes, e.g. once themes were installed.
+    pub fn clear(&mut self) {
+        self.themes.clear();
+    }
+
+    /// Finds the icon `name` like [`find`](Self::find) and loads it at
+    /// `size * scale` pixels. Scalable icons are rasterized at that size,
+    /// others are resized to fit into it if they don't match, keeping their
+    /// aspect ratio and padded with transparency.
+    #[cfg(feature = "texture_renderer")]
+    pub fn load(
+        &mut self,
+        theme: &str,
+        name: &str,
+        size: u32,
+        scale: u32,
+    ) -> anyhow::Result<Option<Image>> {
+        let Some(file) = self.find(theme, name, size, scale) else {
+            return Ok(None);
+        };
+
+        load(&file, size.saturating_mul(scale.max(1))).map(Some)
+    }
+}
+
+#[cfg(feature = "texture_renderer")]
+fn load(file: &Path, pixels: u32) -> anyhow::Result<Image> {
+    #[cfg(feature = "svg")]
+    if file.extension().is_some_and(|extension| extension == "svg") {
+        return Ok(Image::from_svg(&std::fs::read(file)?, pixels, pixels)?);
+    }
+
+    let image = Image::open(file)?;
+    let (width, height) = image.size();
+    if (width, height) == (pixels, pixels) {
+        return Ok(image);
+    }
+
+    // Unthemed icons, e.g. in `/usr/share/pixmaps`, aren't always square
+    let fit = |side: u32, other: u32| {
+        let longest = other.max(side) as u64;
+        ((side as u64 * pixels as u64 + longest / 2) / longest).max(1) as u32
+    };
+    Ok(image
+        .resize_to_fit(fit(width, height), fit(height, width))?
+        .pad_rgba(pixels, pixels, &[0, 0, 0, 0]))
+}
@@ -0,0 +1,270 @@
+use super::{Image, ImageError};
+use crate::texture_renderer::{TextureId, TextureRenderer};
+use image::{AnimationDecoder, ImageFormat};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Delays below this are shown for [`DEFAULT_DELAY`] instead, like browsers
+/// do, since many GIFs are authored with delays of 0.
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Frame of an [`AnimatedImage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub image: Image,
+    /// How long the frame is shown.
+    pub delay: Duration,
+}
+
+/// All frames of an animated GIF or WebP together with a playback clock,
+/// e.g. for animated avatars.
+///
+/// Frames are uploaded to the texture renderer when they are first shown
+/// and drawn from the atlas afterwards, until they get evicted.
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::image::AnimatedImage;
+///
+/// let mut avatar = AnimatedImage::from_bytes(&data)?;
+/// avatar.play(Instant::now());
+///
+/// // Every frame
+/// let now = Instant::now();
+/// let texture = avatar.texture(&mut texture_renderer, &queue, now)?;
+/// buffer.set_texture(texture);
+///
+/// if let Some(delay) = avatar.next_frame_in(now) {
+///     schedule_redraw(delay);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AnimatedImage {
+    frames: Vec<Frame>,
+    duration: Duration,
+    // Handles of the frames uploaded so far
+    textures: Vec<Option<TextureId>>,
+    // Playing since the instant, with the position at that instant
+    started: Option<Instant>,
+    offset: Duration,
+}
+
+impl AnimatedImage {
+    /// Decodes all frames of an image, sniffing its format. Images that
+    /// aren't animated have a single frame.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        let format = image::guess_format(bytes).map_err(|_| ImageError::UnknownFormat)?;
+        if !format.reading_enabled() {
+            return Err(ImageError::Unsupported(format));
+        }
+
+        let frames = match format {
+            #[cfg(feature = "gif")]
+            ImageFormat::Gif => image::codecs::gif::GifDecoder::new(Cursor::new(bytes))
+                .and_then(|decoder| decoder.into_frames().collect_frames())
+                .map_err(ImageError::Decode)?,
+            ImageFormat::WebP => {
+                let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(bytes))
+                    .map_err(ImageError::Decode)?;
+
+                match decoder.has_animation() {
+                    true => decoder
+                        .into_frames()
+                        .collect_frames()
+                        .map_err(ImageError::Decode)?,
+                    false => {
+                        let image = image::DynamicImage::from_decoder(decoder)
+                            .map_err(ImageError::Decode)?;
+                        return Ok(Self::from(Image::from(image)));
+                    }
+                }
+            }
+            _ => return Image::from_bytes(bytes).map(Self::from),
+        };
+
+        let frames = frames
+            .into_iter()
+            .map(|frame| {
+                let delay = match Duration::from(frame.delay()) {
+                    delay if delay < MIN_DELAY => DEFAULT_DELAY,
+                    delay => delay,
+                };
+
+                Frame {
+                    image: Image::from(frame.into_buffer()),
+                    delay,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Animations without any frames decode to nothing to show
+        if frames.is_empty() {
+            return Err(ImageError::Decode(image::ImageError::Decoding(
+                image::error::DecodingError::new(format.into(), "No frames"),
+            )));
+        }
+
+        Ok(Self::new(frames))
+    }
+
+    /// Like [`from_bytes`](Self::from_bytes), reading the image from `reader`.
+    pub fn from_reader<R>(mut reader: R) -> Result<Self, ImageError>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn open<T>(path: T) -> anyhow::Result<Self>
+    where
+        T: AsRef<Path>,
+    {
+        Ok(Self::from_bytes(&std::fs::read(path)?)?)
+    }
+
+    /// Paused at the first of `frames`, which can't be empty.
+    pub fn new(frames: Vec<Frame>) -> Self {
+        assert!(!frames.is_empty(), "An animated image needs a frame");
+
+        Self {
+            duration: frames.iter().map(|frame| frame.delay).sum(),
+            textures: vec![None; frames.len()],
+            frames,
+            started: None,
+            offset: Duration::ZERO,
+        }
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// Duration of one loop through all frames.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Starts or resumes playback at `now`.
+    pub fn play(&mut self, now: Instant) {
+        if self.started.is_none() {
+            self.started = Some(now);
+        }
+    }
+
+    /// Pauses playback at `now`, keeping the current frame.
+    pub fn pause(&mut self, now: Instant) {
+        self.offset = self.elapsed(now);
+        self.started = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Moves playback to `position`, e.g. [`Duration::ZERO`] to restart it.
+    pub fn seek(&mut self, position: Duration, now: Instant) {
+        self.offset = position;
+        self.started = self.started.map(|_| now);
+    }
+
+    /// Time played until `now`, across all loops.
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        self.offset
+            + self.started.map_or(Duration::ZERO, |started| {
+                now.saturating_duration_since(started)
+            })
+    }
+
+    /// Index of the frame shown `elapsed` into playback, looping forever.
+    pub fn frame_index(&self, elapsed: Duration) -> usize {
+        if self.duration.is_zero() {
+            return 0;
+        }
+
+        let mut position =
+            Duration::from_nanos((elapsed.as_nanos() % self.duration.as_nanos()) as u64);
+        self.frames
+            .iter()
+            .position(|frame| match position.checked_sub(frame.delay) {
+                Some(rest) => {
+                    position = rest;
+                    false
+                }
+                None => true,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Frame shown at `now`.
+    pub fn frame(&self, now: Instant) -> &Frame {
+        &self.frames[self.frame_index(self.elapsed(now))]
+    }
+
+    /// Time from `now` until the next frame is shown, or `None` when
+    /// nothing changes, i.e. while paused or for a single frame.
+    pub fn next_frame_in(&self, now: Instant) -> Option<Duration> {
+        if !self.is_playing() || !self.is_animated() || self.duration.is_zero() {
+            return None;
+        }
+
+        let elapsed = self.elapsed(now);
+        let position = elapsed.as_nanos() % self.duration.as_nanos();
+        let end = self.frames[..=self.frame_index(elapsed)]
+            .iter()
+            .map(|frame| frame.delay)
+            .sum::<Duration>();
+
+        Some(end - Duration::from_nanos(position as u64))
+    }
+
+    /// Texture of the frame shown at `now`. Frames are uploaded the first
+    /// time they are shown and again only once `renderer` evicted them.
+    pub fn texture(
+        &mut self,
+        renderer: &mut TextureRenderer,
+        queue: &wgpu::Queue,
+        now: Instant,
+    ) -> anyhow::Result<TextureId> {
+        let index = self.frame_index(self.elapsed(now));
+
+        match self.textures[index] {
+            Some(texture) if renderer.contains(texture) => Ok(texture),
+            _ => {
+                let image = &self.frames[index].image;
+                let texture = renderer.upload(queue, image.width, image.height, &image.data)?;
+                self.textures[index] = Some(texture);
+
+                Ok(texture)
+            }
+        }
+    }
+
+    /// Frees the atlas space of all uploaded frames.
+    pub fn release(&mut self, renderer: &mut TextureRenderer) {
+        self.textures
+            .iter_mut()
+            .filter_map(Option::take)
+            .for_each(|texture| {
+                renderer.remove(texture);
+            });
+    }
+}
+
+impl From<Image> for AnimatedImage {
+    fn from(value: Image) -> Self {
+        Self::new(vec![Frame {
+            image: value,
+            delay: Duration::ZERO,
+        }])
+    }
+}
@@ -1,9 +1,79 @@
+mod animated;
+#[cfg(feature = "svg")]
+mod svg;
+
 use fast_image_resize::{self as fr, PixelType};
 use fast_image_resize::{FilterType, ResizeAlg, ResizeOptions, Resizer};
-use image::{DynamicImage, RgbaImage};
+use image::{DynamicImage, ImageFormat, RgbaImage};
 use serde::{Deserialize, Serialize};
+use std::io::Read;
 use std::path::Path;
 
+pub use animated::{AnimatedImage, Frame};
+#[cfg(feature = "svg")]
+pub use svg::SvgCache;
+
+/// Why an image couldn't be decoded.
+#[derive(Debug)]
+pub enum ImageError {
+    /// The data isn't in any format known to the decoder.
+    UnknownFormat,
+    /// The data is in a format whose cargo feature isn't enabled.
+    Unsupported(ImageFormat),
+    Decode(image::ImageError),
+    #[cfg(feature = "svg")]
+    Svg(resvg::usvg::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "Unknown image format"),
+            Self::Unsupported(format) => match feature(*format) {
+                Some(feature) => write!(
+                    f,
+                    "Unsupported image format {format:?}, enable the `{feature}` feature"
+                ),
+                None => write!(f, "Unsupported image format {format:?}"),
+            },
+            Self::Decode(err) => write!(f, "Failed to decode image: {err}"),
+            #[cfg(feature = "svg")]
+            Self::Svg(err) => write!(f, "Failed to parse SVG: {err}"),
+            Self::Io(err) => write!(f, "Failed to read image: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(err) => Some(err),
+            #[cfg(feature = "svg")]
+            Self::Svg(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Cargo feature of this crate that decodes `format`, if there is one.
+fn feature(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Png => Some("png"),
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::Bmp => Some("bmp"),
+        ImageFormat::Tiff => Some("tiff"),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Image {
     width: u32,
@@ -12,23 +82,63 @@ pub struct Image {
 }
 
 impl Image {
+    /// Decodes the image at `path`, whose format is sniffed from its
+    /// contents, or taken from its extension for formats without a
+    /// signature.
     pub fn open<T>(path: T) -> anyhow::Result<Self>
     where
         T: AsRef<Path>,
     {
-        let image = image::open(path)?;
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
 
-        let rgba_image = image.to_rgba8();
+        let format = image::guess_format(&bytes)
+            .or_else(|_| ImageFormat::from_path(path))
+            .map_err(|_| ImageError::UnknownFormat)?;
 
-        let width = rgba_image.width();
-        let height = rgba_image.height();
-        let data = rgba_image.as_raw().clone();
+        Ok(Self::decode(&bytes, format)?)
+    }
 
-        Ok(Self {
-            width,
-            height,
-            data,
-        })
+    /// Decodes an encoded image, e.g. the PNG of a notification icon,
+    /// sniffing its format.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use moxui::image::{Image, ImageError};
+    ///
+    /// match Image::from_bytes(&icon_data) {
+    ///     Ok(image) => icons.push(image),
+    ///     Err(ImageError::Unsupported(format)) => eprintln!("No decoder for {format:?}"),
+    ///     Err(err) => return Err(err.into()),
+    /// }
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        let format = image::guess_format(bytes).map_err(|_| ImageError::UnknownFormat)?;
+
+        Self::decode(bytes, format)
+    }
+
+    /// Like [`from_bytes`](Self::from_bytes), reading the image from `reader`.
+    pub fn from_reader<R>(mut reader: R) -> Result<Self, ImageError>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    fn decode(bytes: &[u8], format: ImageFormat) -> Result<Self, ImageError> {
+        if !format.reading_enabled() {
+            return Err(ImageError::Unsupported(format));
+        }
+
+        let image =
+            image::load_from_memory_with_format(bytes, format).map_err(ImageError::Decode)?;
+
+        Ok(Self::from(image))
     }
 
     pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
@@ -121,9 +231,15 @@ impl Image {
     }
 
     pub fn pad(self, width: u32, height: u32, color: &[u8; 3]) -> Self {
+        self.pad_rgba(width, height, &[color[0], color[1], color[2], 255])
+    }
+
+    /// Like [`pad`](Self::pad), with a straight alpha `color`, e.g. to pad
+    /// with transparency.
+    pub fn pad_rgba(self, width: u32, height: u32, color: &[u8; 4]) -> Self {
         let channels = 4;
 
-        let color = [color[0], color[1], color[2], 255];
+        let color = *color;
 
         let mut padded = Vec::with_capacity((width * height * channels) as usize);
 
@@ -144,10 +260,10 @@ impl Image {
             padded.extend_from_slice(&color);
         });
 
-        // Calculate left and right border widths. `u32::div` rounds toward 0, so, if `img_w` is odd,
-        // add an extra pixel to the right border to ensure the row is the correct width.
+        // Calculate left and right border widths. `u32::div` rounds toward 0, so the right border
+        // gets the extra pixel of an odd difference to keep the row the correct width.
         let left_border_w = (width as usize - img_w) / 2;
-        let right_border_w = left_border_w + (img_w % 2);
+        let right_border_w = width as usize - img_w - left_border_w;
 
         (0..img_h).for_each(|row| {
             (0..left_border_w).for_each(|_| {
@@ -199,7 +315,7 @@ impl Image {
         Self {
             width,
             height,
-            data: data.into(),
+            data,
         }
     }
 
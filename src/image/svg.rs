@@ -0,0 +1,111 @@
+use super::{Image, ImageError};
+use resvg::{tiny_skia, usvg};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+impl Image {
+    /// Rasterizes an SVG to `width`x`height` pixels, scaled to fit and
+    /// centered without changing its aspect ratio.
+    pub fn from_svg(data: &[u8], width: u32, height: u32) -> Result<Self, ImageError> {
+        rasterize(data, &usvg::Options::default(), width, height)
+    }
+}
+
+fn rasterize(
+    data: &[u8],
+    options: &usvg::Options,
+    width: u32,
+    height: u32,
+) -> Result<Image, ImageError> {
+    let tree = usvg::Tree::from_data(data, options).map_err(ImageError::Svg)?;
+
+    // A pixmap can't be empty
+    let (width, height) = (width.max(1), height.max(1));
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).expect("size is not zero");
+
+    let size = tree.size();
+    let scale = (width as f32 / size.width()).min(height as f32 / size.height());
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(
+        (width as f32 - size.width() * scale) / 2.,
+        (height as f32 - size.height() * scale) / 2.,
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // Images hold straight alpha, pixmaps premultiplied alpha
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        data,
+    })
+}
+
+/// Rasters of SVG files, e.g. icons, kept per size and scale.
+///
+/// Asking for a new scale rasterizes the SVG again instead of resizing an
+/// existing raster, so icons stay crisp on HiDPI outputs.
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::image::SvgCache;
+///
+/// let mut svgs = SvgCache::default();
+///
+/// // 32x32 logical pixels on an output with a scale of 2
+/// let icon = svgs.get("/usr/share/icons/hicolor/scalable/apps/firefox.svg", 32, 2.)?;
+/// assert_eq!(icon.size(), (64, 64));
+/// ```
+#[derive(Debug, Default)]
+pub struct SvgCache {
+    rasters: HashMap<(PathBuf, u32, u32), Image>,
+}
+
+impl SvgCache {
+    /// Raster of the SVG at `path`, `size`x`size` logical pixels large at
+    /// `scale`, reading and rasterizing it if it's not cached yet.
+    pub fn get<P>(&mut self, path: P, size: u32, scale: f32) -> Result<&Image, ImageError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let key = (path.to_path_buf(), size, scale.to_bits());
+
+        if !self.rasters.contains_key(&key) {
+            let data = std::fs::read(path)?;
+
+            // Images the SVG links to are relative to it
+            let options = usvg::Options {
+                resources_dir: path.parent().map(Path::to_path_buf),
+                ..Default::default()
+            };
+
+            let pixels = (size as f32 * scale).round() as u32;
+            let image = rasterize(&data, &options, pixels, pixels)?;
+            self.rasters.insert(key.clone(), image);
+        }
+
+        Ok(&self.rasters[&key])
+    }
+
+    /// Drops the rasters of the SVG at `path`, e.g. once it changed on disk.
+    pub fn remove<P>(&mut self, path: P)
+    where
+        P: AsRef<Path>,
+    {
+        self.rasters
+            .retain(|(cached, _, _), _| cached != path.as_ref());
+    }
+
+    pub fn clear(&mut self) {
+        self.rasters.clear();
+    }
+}
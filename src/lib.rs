@@ -1,5 +1,7 @@
 mod renderers;
 
+#[cfg(any(feature = "shape_renderer", feature = "texture_renderer"))]
+pub use renderers::backdrop;
 #[cfg(feature = "shape_renderer")]
 pub use renderers::shape_renderer;
 #[cfg(feature = "text_renderer")]
@@ -8,7 +10,17 @@ pub use renderers::text_renderer;
 pub use renderers::texture_renderer;
 
 pub mod buffers;
+pub mod clip;
+pub mod icon;
 pub mod viewport;
 
 #[cfg(feature = "texture_renderer")]
 pub mod image;
+#[cfg(feature = "texture_renderer")]
+pub mod offscreen;
+#[cfg(all(
+    feature = "shape_renderer",
+    feature = "texture_renderer",
+    feature = "text_renderer"
+))]
+pub mod scene;
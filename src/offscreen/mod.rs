@@ -0,0 +1,393 @@
+use crate::image::Image;
+#[cfg(feature = "shape_renderer")]
+use crate::shape_renderer::ShapeRenderer;
+#[cfg(feature = "text_renderer")]
+use crate::text_renderer::TextRenderer;
+use crate::texture_renderer::{self, TextureRenderer};
+use crate::viewport::{Resolution, Viewport};
+use anyhow::Context;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+/// Renderers drawn by [`Offscreen::render`].
+///
+/// Shapes and text share one pass, textures are drawn in passes of their
+/// own afterwards. All of them share the depth buffer, so they are layered
+/// by their depth.
+#[derive(Default)]
+pub struct Renderers<'a> {
+    #[cfg(feature = "shape_renderer")]
+    pub shapes: Option<&'a ShapeRenderer>,
+    #[cfg(feature = "text_renderer")]
+    pub text: Option<&'a TextRenderer>,
+    pub textures: Option<&'a TextureRenderer>,
+}
+
+/// Render target that isn't backed by a window surface.
+///
+/// Useful for thumbnails, screenshots and rendering without a display,
+/// e.g. on a software adapter. Renderers drawing into it have to be
+/// created with [`Offscreen::FORMAT`].
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::offscreen::{Offscreen, Renderers};
+///
+/// let offscreen = Offscreen::new(&device, 256, 256);
+/// viewport.update(&queue, offscreen.resolution());
+///
+/// shape_renderer.prepare(&device, &queue, &shapes);
+/// let image = offscreen
+///     .render(&device, &queue, &viewport, Renderers {
+///         shapes: Some(&shape_renderer),
+///         ..Default::default()
+///     })
+///     .await?;
+/// ```
+///
+/// On native backends the readback only completes while the device is
+/// polled, e.g. by an event loop calling
+/// `device.poll(wgpu::PollType::Poll)`. Without one,
+/// [`render_blocking`](Self::render_blocking) waits for the GPU instead.
+pub struct Offscreen {
+    width: u32,
+    height: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+}
+
+impl Offscreen {
+    /// Format of the color target.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let (depth_texture, depth_view) =
+            texture_renderer::create_depth_buffer(device, width, height);
+
+        Self {
+            width,
+            height,
+            texture,
+            view,
+            _depth_texture: depth_texture,
+            depth_view,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Resolution to update the [`Viewport`] with before rendering.
+    pub fn resolution(&self) -> Resolution {
+        Resolution {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+
+    /// Clears the target, draws the prepared `renderers` into it and reads
+    /// the result back.
+    ///
+    /// The returned future resolves once the device has been polled after
+    /// the GPU finished, see [`read`](Self::read).
+    pub async fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        viewport: &Viewport,
+        renderers: Renderers<'_>,
+    ) -> anyhow::Result<Image> {
+        self.draw(device, queue, viewport, renderers)?;
+        self.read(device, queue).await
+    }
+
+    /// Like [`render`](Self::render), but blocks until the GPU finished.
+    pub fn render_blocking(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        viewport: &Viewport,
+        renderers: Renderers<'_>,
+    ) -> anyhow::Result<Image> {
+        self.draw(device, queue, viewport, renderers)?;
+        self.read_blocking(device, queue)
+    }
+
+    fn draw(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        viewport: &Viewport,
+        renderers: Renderers<'_>,
+    ) -> anyhow::Result<()> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("offscreen_encoder"),
+        });
+
+        #[cfg(feature = "text_renderer")]
+        if let Some(text) = renderers.text {
+            text.render_effects(&mut encoder)?;
+        }
+
+        // Nothing draws into the pass without the shape and text renderers
+        #[allow(unused_mut)]
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("offscreen_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+
+        #[cfg(feature = "shape_renderer")]
+        if let Some(shapes) = renderers.shapes {
+            shapes.render(&mut render_pass, viewport);
+        }
+
+        #[cfg(feature = "text_renderer")]
+        if let Some(text) = renderers.text {
+            text.render(&mut render_pass)?;
+        }
+
+        drop(render_pass);
+
+        if let Some(textures) = renderers.textures {
+            textures.render_with_depth(&self.view, &self.depth_view, &mut encoder, viewport);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+
+    /// Copies the current contents of the target into an [`Image`] with
+    /// straight alpha.
+    ///
+    /// This doesn't block: on native backends the future only resolves
+    /// once the device is polled after the copy finished, so something else
+    /// has to keep polling it. Use [`read_blocking`](Self::read_blocking)
+    /// to wait for the GPU instead.
+    pub async fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Image> {
+        let buffer = self.copy(device, queue);
+        let mapped = MapFuture::new(&buffer.slice(..));
+
+        // Picks up a copy that already finished without waiting for it
+        device.poll(wgpu::PollType::Poll)?;
+        mapped.await?;
+
+        self.image(&buffer)
+    }
+
+    /// Like [`read`](Self::read), but blocks until the GPU finished.
+    pub fn read_blocking(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<Image> {
+        let buffer = self.copy(device, queue);
+        let mapped = MapFuture::new(&buffer.slice(..));
+
+        device.poll(wgpu::PollType::wait_indefinitely())?;
+        mapped
+            .result()
+            .context("Readback buffer wasn't mapped after waiting for the GPU")??;
+
+        self.image(&buffer)
+    }
+
+    /// Rows of a texture copy have to be aligned.
+    fn bytes_per_row(&self) -> u32 {
+        (self.width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+    }
+
+    /// Submits a copy of the target into a new readback buffer.
+    fn copy(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Buffer {
+        let bytes_per_row = self.bytes_per_row();
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen_readback_buffer"),
+            size: bytes_per_row as u64 * self.height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("offscreen_readback_encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        buffer
+    }
+
+    /// Strips the row padding off the mapped readback `buffer`.
+    fn image(&self, buffer: &wgpu::Buffer) -> anyhow::Result<Image> {
+        let unpadded_bytes_per_row = self.width * 4;
+
+        let mut data = {
+            let padded = buffer.slice(..).get_mapped_range();
+            let mut data = Vec::with_capacity((unpadded_bytes_per_row * self.height) as usize);
+            padded
+                .chunks_exact(self.bytes_per_row() as usize)
+                .for_each(|row| data.extend_from_slice(&row[..unpadded_bytes_per_row as usize]));
+            data
+        };
+        buffer.unmap();
+
+        data.chunks_exact_mut(4).for_each(unpremultiply);
+
+        Image::from_raw(self.width, self.height, data)
+            .context("Readback doesn't match the offscreen target size")
+    }
+}
+
+/// The renderers blend with premultiplied alpha in linear space, so colors
+/// are divided by alpha before being encoded to sRGB again.
+fn unpremultiply(pixel: &mut [u8]) {
+    let alpha = pixel[3];
+    if alpha == 0 || alpha == 255 {
+        return;
+    }
+
+    let alpha = alpha as f32 / 255.;
+    pixel[..3].iter_mut().for_each(|c| {
+        let linear = srgb_to_linear(*c as f32 / 255.) / alpha;
+        *c = (linear_to_srgb(linear.min(1.)) * 255.).round() as u8;
+    });
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+struct MapState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves once a buffer slice has been mapped for reading.
+struct MapFuture {
+    state: Arc<Mutex<MapState>>,
+}
+
+impl MapFuture {
+    fn new(slice: &wgpu::BufferSlice) -> Self {
+        let state = Arc::new(Mutex::new(MapState {
+            result: None,
+            waker: None,
+        }));
+
+        let callback_state = Arc::clone(&state);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let mut state = callback_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        Self { state }
+    }
+
+    /// Result of the mapping if it already completed.
+    fn result(&self) -> Option<Result<(), wgpu::BufferAsyncError>> {
+        self.state.lock().unwrap().result.take()
+    }
+}
+
+impl Future for MapFuture {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,501 @@
+use crate::buffers::{self, DataDescription, GpuBuffer, instance::InstanceBuffer};
+use crate::clip::{ClipBuffer, ClipId, ClipStack};
+use crate::renderers::blur::{BlurLevels, blur_level};
+use crate::viewport;
+use std::ops::Range;
+
+/// Region of the target that's blurred before a translucent surface is
+/// drawn over it, like CSS `backdrop-filter: blur()`.
+///
+/// Shapes and textures with a backdrop blur return one from
+/// [`Shape::backdrop`](crate::shape_renderer::Shape::backdrop) and
+/// [`TextureArea::backdrop`](crate::texture_renderer::TextureArea::backdrop).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backdrop {
+    /// `[left, top, width, height]` in pixels, before `transform`.
+    pub rect: [f32; 4],
+    /// Corner radii in pixels as `[top_left, top_right, bottom_right, bottom_left]`.
+    pub radius: [f32; 4],
+    /// Affine transform `[a, b, c, d, e, f]` relative to the top left corner
+    /// of `rect`, in the layout of `Transforms::matrix`.
+    pub transform: [f32; 6],
+    /// Standard deviation of the blur in pixels.
+    pub blur: f32,
+    pub depth: f32,
+    /// Clips of the [`ClipStack`] passed to
+    /// [`BackdropRenderer::prepare_clips`] the blur is confined to.
+    pub clip: ClipId,
+}
+
+impl Default for Backdrop {
+    fn default() -> Self {
+        Self {
+            rect: [0.; 4],
+            radius: [0.; 4],
+            transform: [1., 0., 0., 1., 0., 0.],
+            blur: 0.,
+            depth: 0.,
+            clip: ClipId::default(),
+        }
+    }
+}
+
+impl Backdrop {
+    /// Returns the pixels `[left, top, right, bottom]` the region covers
+    /// once transformed, grown by `outset` and clamped to `size`.
+    fn pixel_bounds(&self, outset: [f32; 2], size: [u32; 2]) -> [u32; 4] {
+        let [a, b, c, d, e, f] = self.transform;
+        let [left, top, width, height] = self.rect;
+
+        let (min, max) = [(0., 0.), (width, 0.), (0., height), (width, height)]
+            .into_iter()
+            .map(|(x, y)| (left + a * x + c * y + e, top + b * x + d * y + f))
+            .fold(
+                ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]),
+                |(min, max), (x, y)| {
+                    (
+                        [min[0].min(x), min[1].min(y)],
+                        [max[0].max(x), max[1].max(y)],
+                    )
+                },
+            );
+
+        let clamp = |value: f32, size: u32| value.clamp(0., size as f32) as u32;
+        [
+            clamp((min[0] - outset[0]).floor(), size[0]),
+            clamp((min[1] - outset[1]).floor(), size[1]),
+            clamp((max[0] + outset[0]).ceil(), size[0]),
+            clamp((max[1] + outset[1]).ceil(), size[1]),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct BackdropInstance {
+    rect: [f32; 4],
+    radius: [f32; 4],
+    transform: [f32; 4], // [a, b, c, d] of `Backdrop::transform`
+    translate: [f32; 2], // [e, f] of `Backdrop::transform`
+    kernel: [u32; 2],    // [first, count] of the weights and offsets along either axis
+    depth: f32,
+    bounds: [f32; 4], // pixels the horizontal pass writes
+    clip: ClipId,
+}
+
+impl DataDescription for BackdropInstance {
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+
+    const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x2,
+        5 => Uint32x2,
+        6 => Float32,
+        7 => Float32x4,
+        8 => Uint32x2,
+    ];
+}
+
+impl buffers::instance::Instance for BackdropInstance {}
+
+/// Blurs what has already been drawn behind [`Backdrop`] regions, for
+/// frosted glass panels.
+///
+/// Every backdrop copies the part of the target it reads into a texture of
+/// its own, blurs it in two passes and draws the result back clipped to its
+/// rounded rect. Large blurs run on a downsampled copy, like those of
+/// textures. The backdrop isn't tinted, so draw the surface itself with a
+/// translucent fill on top afterwards. The target has to be created with
+/// [`wgpu::TextureUsages::COPY_SRC`].
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::backdrop::BackdropRenderer;
+///
+/// let mut backdrop_renderer = BackdropRenderer::new(&device, format, width, height);
+///
+/// // Drawn after the wallpaper, but before the panel
+/// let backdrops = panel.backdrop().into_iter().collect::<Vec<_>>();
+/// backdrop_renderer.prepare(&device, &queue, &backdrops);
+/// backdrop_renderer.render(&surface_view, None, &mut encoder, &viewport);
+/// ```
+pub struct BackdropRenderer {
+    format: wgpu::TextureFormat,
+    horizontal_pipeline: wgpu::RenderPipeline,
+    vertical_pipeline: wgpu::RenderPipeline,
+    vertical_depth_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: buffers::VertexBuffer,
+    index_buffer: buffers::IndexBuffer,
+    instance_buffer: InstanceBuffer<BackdropInstance>,
+    levels: BlurLevels,
+    /// Pixels of the target each prepared backdrop reads.
+    regions: Vec<[u32; 4]>,
+    /// Level each prepared backdrop is blurred at.
+    instance_levels: Vec<u32>,
+    clips: ClipBuffer,
+}
+
+impl BackdropRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let levels = BlurLevels::new(device, format, width, height);
+
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("uniform_bind_group_layout"),
+            });
+
+        let clips = ClipBuffer::new(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("backdrop_pipeline_layout"),
+            bind_group_layouts: &[
+                levels.bind_group_layout(),
+                &uniform_bind_group_layout,
+                clips.bind_group_layout(),
+            ],
+            immediate_size: 0,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("backdrop_shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}{}{}",
+                    include_str!("shader.wgsl"),
+                    BlurLevels::SHADER,
+                    ClipBuffer::SHADER
+                )
+                .into(),
+            ),
+        });
+
+        let buffers = [buffers::Vertex::desc(), BackdropInstance::desc()];
+        let create_pipeline = |label, vertex_entry, fragment_entry, blend, depth_stencil| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some(vertex_entry),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers: &buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(fragment_entry),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil,
+                multisample: wgpu::MultisampleState::default(),
+                cache: None,
+                multiview_mask: None,
+            })
+        };
+
+        // The surface drawn over the backdrop has the same depth, so the
+        // backdrop only tests against the depth buffer
+        let depth_stencil = wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+
+        let horizontal_pipeline = create_pipeline(
+            "backdrop_horizontal_pipeline",
+            "vs_horizontal",
+            "fs_horizontal",
+            None,
+            None,
+        );
+        let vertical_pipeline = create_pipeline(
+            "backdrop_vertical_pipeline",
+            "vs_vertical",
+            "fs_vertical",
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            None,
+        );
+        let vertical_depth_pipeline = create_pipeline(
+            "backdrop_vertical_depth_pipeline",
+            "vs_vertical",
+            "fs_vertical",
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            Some(depth_stencil),
+        );
+
+        let vertex_buffer = buffers::VertexBuffer::new(
+            device,
+            &[
+                buffers::Vertex {
+                    position: [0.0, 0.0],
+                },
+                buffers::Vertex {
+                    position: [1.0, 0.0],
+                },
+                buffers::Vertex {
+                    position: [0.0, 1.0],
+                },
+                buffers::Vertex {
+                    position: [1.0, 1.0],
+                },
+            ],
+        );
+
+        let index_buffer = buffers::IndexBuffer::new(device, &[0, 1, 2, 3]);
+
+        Self {
+            format,
+            horizontal_pipeline,
+            vertical_pipeline,
+            vertical_depth_pipeline,
+            vertex_buffer,
+            index_buffer,
+            instance_buffer: InstanceBuffer::new(device, &[]),
+            levels,
+            regions: Vec::new(),
+            instance_levels: Vec::new(),
+            clips,
+        }
+    }
+
+    /// Resizes the textures the backdrops are blurred in. Call this
+    /// whenever the render target changes size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.levels.resize(device, width, height, self.format);
+    }
+
+    /// Uploads the clips that [`Backdrop::clip`] refers to.
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.clips.prepare(device, queue, clips);
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, backdrops: &[Backdrop]) {
+        let texture = self.levels.source_view().texture();
+        let size = [texture.width(), texture.height()];
+
+        self.regions.clear();
+        self.instance_levels.clear();
+
+        let sigmas = backdrops
+            .iter()
+            .map(|backdrop| blur_level([backdrop.blur; 2]))
+            .collect::<Vec<_>>();
+        self.levels
+            .prepare_kernels(device, queue, sigmas.iter().map(|(_, [sigma, _])| *sigma));
+
+        let instances = backdrops
+            .iter()
+            .zip(sigmas)
+            .map(|(backdrop, (level, [sigma, _]))| {
+                // The gaussian is negligible past three sigmas, and the
+                // kernel's offsets reach up to a texel of its level further
+                let factor = (1 << level) as f32;
+                let reach = factor * (2. + (sigma * 3.).ceil());
+                // Every halving can pull in a texel from outside of the
+                // copy, which the padding keeps away from what's read
+                let padding = (2 << level) as f32;
+                self.regions
+                    .push(backdrop.pixel_bounds([reach + factor + padding; 2], size));
+                self.instance_levels.push(level);
+
+                // Rows above and below are blurred too, so that the
+                // vertical pass has something to read, and a texel to the
+                // sides so that it can filter the edges of downsampled levels
+                let bounds = backdrop
+                    .pixel_bounds([factor, reach], size)
+                    .map(|b| b as f32);
+
+                let [a, b, c, d, e, f] = backdrop.transform;
+                BackdropInstance {
+                    rect: backdrop.rect,
+                    radius: backdrop.radius,
+                    transform: [a, b, c, d],
+                    translate: [e, f],
+                    kernel: self.levels.kernel(sigma),
+                    depth: backdrop.depth,
+                    bounds,
+                    clip: backdrop.clip,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if instances.is_empty() {
+            self.instance_buffer.write(queue, &[]);
+            return;
+        }
+
+        let needed_buffer_size = std::mem::size_of_val(instances.as_slice()) as u64;
+
+        if self.instance_buffer.capacity() < needed_buffer_size {
+            self.instance_buffer = InstanceBuffer::with_size(
+                device,
+                needed_buffer_size.max(self.instance_buffer.capacity() * 2),
+            );
+        }
+
+        self.instance_buffer.write(queue, &instances);
+    }
+
+    /// Blurs the backdrops of all prepared regions of `target`.
+    ///
+    /// With a `depth_view`, backdrops are tested against it like the other
+    /// renderers, but don't write to it.
+    pub fn render(
+        &self,
+        target: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
+        encoder: &mut wgpu::CommandEncoder,
+        viewport: &viewport::Viewport,
+    ) {
+        self.render_range(
+            target,
+            depth_view,
+            encoder,
+            viewport,
+            0..self.instance_buffer.size(),
+        );
+    }
+
+    /// Blurs the backdrops of only the prepared regions in `instances`.
+    pub(crate) fn render_range(
+        &self,
+        target: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
+        encoder: &mut wgpu::CommandEncoder,
+        viewport: &viewport::Viewport,
+        instances: Range<u32>,
+    ) {
+        // One at a time, so that a backdrop sees the ones before it
+        instances.for_each(|i| {
+            let texture = target.texture();
+            let level = self.instance_levels[i as usize];
+            let [left, top, right, bottom] = self.regions[i as usize];
+            let right = right.min(texture.width());
+            let bottom = bottom.min(texture.height());
+            if left >= right || top >= bottom {
+                return;
+            }
+
+            let origin = wgpu::Origin3d {
+                x: left,
+                y: top,
+                z: 0,
+            };
+            encoder.copy_texture_to_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyTextureInfo {
+                    texture: self.levels.source_view().texture(),
+                    mip_level: 0,
+                    origin,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d {
+                    width: right - left,
+                    height: bottom - top,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            self.levels.downsample(
+                encoder,
+                level,
+                [left as f32, top as f32, right as f32, bottom as f32],
+            );
+
+            let mut horizontal_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("backdrop_horizontal_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.levels.horizontal_view(level),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+
+            horizontal_pass.set_pipeline(&self.horizontal_pipeline);
+            horizontal_pass.set_bind_group(0, &self.levels.bind_groups(level)[0], &[]);
+            horizontal_pass.set_bind_group(1, &viewport.bind_group, &[]);
+            horizontal_pass.set_bind_group(2, self.clips.bind_group(), &[]);
+            horizontal_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            horizontal_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            horizontal_pass
+                .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            horizontal_pass.draw_indexed(0..self.index_buffer.size(), 0, i..i + 1);
+
+            drop(horizontal_pass);
+
+            let mut vertical_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("backdrop_vertical_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: depth_view.map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                ..Default::default()
+            });
+
+            vertical_pass.set_pipeline(match depth_view {
+                Some(_) => &self.vertical_depth_pipeline,
+                None => &self.vertical_pipeline,
+            });
+            vertical_pass.set_bind_group(0, &self.levels.bind_groups(level)[1], &[]);
+            vertical_pass.set_bind_group(1, &viewport.bind_group, &[]);
+            vertical_pass.set_bind_group(2, self.clips.bind_group(), &[]);
+            vertical_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            vertical_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            vertical_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            vertical_pass.draw_indexed(0..self.index_buffer.size(), 0, i..i + 1);
+        });
+    }
+}
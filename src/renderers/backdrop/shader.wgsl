@@ -0,0 +1,104 @@
+struct Params {
+    screen_resolution: vec2<u32>,
+    _pad: vec2<u32>,
+};
+@group(1) @binding(0)
+var<uniform> params: Params;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(1) rect: vec4<f32>,
+    @location(2) radius: vec4<f32>,  // [top_left, top_right, bottom_right, bottom_left]
+    @location(3) transform: vec4<f32>,  // linear part of the affine transform, column major
+    @location(4) translate: vec2<f32>,
+    @location(5) kernel: vec2<u32>,  // [first, count] of the weights and offsets
+    @location(6) depth: f32,
+    @location(7) bounds: vec4<f32>,  // [left, top, right, bottom] the horizontal pass covers
+    @location(8) clip: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) local_position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) radius: vec4<f32>,
+    @location(3) @interpolate(flat) kernel: vec2<u32>,
+    @location(4) @interpolate(flat) clip: vec2<u32>,  // [first, count] of the clip rects
+    @location(5) tex_coords: vec2<f32>,
+};
+
+fn to_clip(position: vec2<f32>, depth: f32) -> vec4<f32> {
+    let ndc = (position / vec2<f32>(params.screen_resolution)) * 2.0 - vec2<f32>(1.0, 1.0);
+    return vec4<f32>(ndc.x, -ndc.y, depth, 1.0);
+}
+
+@vertex
+fn vs_horizontal(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let position = mix(instance.bounds.xy, instance.bounds.zw, model.position);
+    out.clip_position = to_clip(position, 0.0);
+    out.kernel = instance.kernel;
+
+    return out;
+}
+
+@vertex
+fn vs_vertical(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let corner = model.position * instance.rect.zw;
+    let transform = mat2x2<f32>(instance.transform.xy, instance.transform.zw);
+    let position = instance.rect.xy + transform * corner + instance.translate;
+
+    out.clip_position = to_clip(position, instance.depth);
+    out.tex_coords = position / vec2<f32>(params.screen_resolution);
+    out.local_position = corner;
+    out.size = instance.rect.zw;
+    out.radius = instance.radius;
+    out.kernel = instance.kernel;
+    out.clip = instance.clip;
+
+    return out;
+}
+
+fn sdf_rounded_rect(p: vec2<f32>, b: vec2<f32>, r: vec4<f32>) -> f32 {
+    let top = select(r.x, r.y, p.x > 0.0);
+    let bottom = select(r.w, r.z, p.x > 0.0);
+    let radius = select(top, bottom, p.y > 0.0);
+    let q = abs(p) - b + radius;
+    return min(max(q.x, q.y), 0.0) + length(max(q, vec2<f32>(0.0))) - radius;
+}
+
+@fragment
+fn fs_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Runs at the resolution of the level it reads
+    let tex_coords = in.clip_position.xy / vec2<f32>(textureDimensions(t_diffuse));
+    return blur(tex_coords, in.kernel, vec2<f32>(1.0, 0.0), vec4<f32>(0.0));
+}
+
+@fragment
+fn fs_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    let half_size = in.size * 0.5;
+    let radius = min(in.radius, vec4<f32>(min(half_size.x, half_size.y)));
+    let dist = sdf_rounded_rect(in.local_position - half_size, half_size, radius);
+    let aa = fwidth(dist) * 0.5;
+    let coverage = smoothstep(-aa, aa, -dist) * clip_coverage(in.clip_position.xy, in.clip);
+
+    // Outside the rounded corners or the clips
+    if coverage < 0.001 {
+        discard;
+    }
+
+    // Premultiplied, like everything else drawn into the target
+    return blur(in.tex_coords, in.kernel, vec2<f32>(0.0, 1.0), vec4<f32>(0.0)) * coverage;
+}
@@ -0,0 +1,676 @@
+use crate::buffers;
+
+/// Largest sigma that's blurred at full resolution. Larger ones are blurred
+/// on a downsampled copy, so that their cost doesn't grow with the sigma.
+pub(crate) const MAX_FULL_RESOLUTION_SIGMA: f32 = 8.0;
+
+/// How many times the content can be halved for large sigmas.
+pub(crate) const MAX_LEVEL: u32 = 4;
+
+fn gaussian_kernel_1d(radius: i32, sigma: f32) -> (Vec<f32>, Vec<f32>) {
+    use std::f32::consts::PI;
+
+    let mut k_values = Vec::with_capacity((2 * radius + 1) as usize);
+    let mut offsets = Vec::with_capacity((2 * radius + 1) as usize);
+    let mut intensity = 0.0;
+
+    for y in -radius..=radius {
+        let y_f = y as f32;
+        let g =
+            1.0 / (2.0 * PI * sigma * sigma).sqrt() * (-y_f * y_f / (2.0 * sigma * sigma)).exp();
+        k_values.push(g);
+        offsets.push(y_f);
+        intensity += g;
+    }
+
+    let mut final_k_values = Vec::new();
+    let mut final_offsets = Vec::new();
+
+    let mut i = 0;
+    while i + 1 < k_values.len() {
+        let a = k_values[i];
+        let b = k_values[i + 1];
+        let k = a + b;
+        let alpha = a / k;
+        let offset = offsets[i] + alpha;
+        final_k_values.push(k / intensity);
+        final_offsets.push(offset);
+        i += 2;
+    }
+
+    if i < k_values.len() {
+        let a = k_values[i];
+        let offset = offsets[i];
+        final_k_values.push(a / intensity);
+        final_offsets.push(offset);
+    }
+
+    (final_k_values, final_offsets)
+}
+
+/// Picks the level a blur of `[x, y]` sigmas runs at and the sigmas it has
+/// there.
+///
+/// The larger sigma decides, so that its kernel stays short. Downsampling
+/// blurs both axes though, so the smaller one ends up blurred at least by
+/// the downsampling, e.g. for a motion blur along one axis.
+pub(crate) fn blur_level(sigma: [f32; 2]) -> (u32, [f32; 2]) {
+    let max_sigma = sigma[0].max(sigma[1]);
+    if max_sigma <= MAX_FULL_RESOLUTION_SIGMA {
+        return (0, sigma);
+    }
+
+    let level = ((max_sigma / MAX_FULL_RESOLUTION_SIGMA).log2().ceil() as u32).min(MAX_LEVEL);
+    let factor = (1 << level) as f32;
+
+    // Averaging 2x2 texels per halving already blurs the content a little
+    let downsampling_variance = (factor * factor - 1.) / 12.;
+    let sigma = sigma.map(|sigma| (sigma * sigma - downsampling_variance).max(0.).sqrt() / factor);
+
+    (level, sigma)
+}
+
+/// Weights and offsets of the kernels.
+type StorageBuffers = (buffers::StorageBuffer<f32>, buffers::StorageBuffer<f32>);
+
+/// How many weights the storage buffers have room for at first.
+const INITIAL_KERNEL_CAPACITY: usize = 64;
+
+/// Sigmas are rounded to steps of `1 / SIGMA_STEPS` before kernels are
+/// built for them, so that animated sigmas find the kernels of earlier
+/// frames.
+const SIGMA_STEPS: f32 = 16.;
+
+struct Kernel {
+    /// Rounded sigma, see [`Kernels::key`].
+    key: u32,
+    /// `[first, count]` in the buffers.
+    range: [u32; 2],
+    last_used: u64,
+}
+
+/// Kernels of the sigmas blurred with, laid out like the storage buffers.
+///
+/// Kernels are kept across frames and new ones appended, so only those get
+/// uploaded. Kernels that weren't used for a frame are dropped only once
+/// new ones wouldn't fit into the buffers anymore.
+#[derive(Default)]
+struct Kernels {
+    kernels: Vec<Kernel>,
+    weights: Vec<f32>,
+    offsets: Vec<f32>,
+    /// Weights and offsets before this are in the buffers already.
+    uploaded: usize,
+    frame: u64,
+}
+
+impl Kernels {
+    fn key(sigma: f32) -> u32 {
+        (sigma.max(0.) * SIGMA_STEPS).round() as u32
+    }
+
+    fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Where the kernel of `sigma` is, if it's built yet, and marks it as
+    /// used in the current frame. Sigmas that round to zero need no kernel.
+    fn get(&mut self, sigma: f32) -> Option<[u32; 2]> {
+        let key = Self::key(sigma);
+        if key == 0 {
+            return Some([0, 0]);
+        }
+
+        let kernel = self.kernels.iter_mut().find(|kernel| kernel.key == key)?;
+        kernel.last_used = self.frame;
+
+        Some(kernel.range)
+    }
+
+    /// Builds the kernels of `sigmas` that are missing. If they don't fit
+    /// into `capacity` weights next to the existing ones, the kernels not
+    /// used in the current frame are dropped first.
+    fn insert_missing(&mut self, sigmas: impl IntoIterator<Item = f32>, capacity: usize) {
+        let mut missing = sigmas
+            .into_iter()
+            .filter(|sigma| self.get(*sigma).is_none())
+            .map(Self::key)
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return;
+        }
+        missing.sort_unstable();
+        missing.dedup();
+
+        let built = missing
+            .into_iter()
+            .map(|key| {
+                let sigma = key as f32 / SIGMA_STEPS;
+                (key, gaussian_kernel_1d((sigma * 3.).ceil() as i32, sigma))
+            })
+            .collect::<Vec<_>>();
+
+        let len = built
+            .iter()
+            .map(|(_, (weights, _))| weights.len())
+            .sum::<usize>();
+        if self.weights.len() + len > capacity {
+            self.compact();
+        }
+
+        built
+            .into_iter()
+            .for_each(|(key, (mut weights, mut offsets))| {
+                self.kernels.push(Kernel {
+                    key,
+                    range: [self.weights.len() as u32, weights.len() as u32],
+                    last_used: self.frame,
+                });
+                self.weights.append(&mut weights);
+                self.offsets.append(&mut offsets);
+            });
+    }
+
+    /// Drops the kernels not used in the current frame and moves the others
+    /// to the front.
+    fn compact(&mut self) {
+        let weights = std::mem::take(&mut self.weights);
+        let offsets = std::mem::take(&mut self.offsets);
+
+        self.kernels.retain(|kernel| kernel.last_used == self.frame);
+        self.kernels.iter_mut().for_each(|kernel| {
+            let [first, count] = kernel.range;
+            let range = first as usize..(first + count) as usize;
+
+            kernel.range = [self.weights.len() as u32, count];
+            self.weights.extend_from_slice(&weights[range.clone()]);
+            self.offsets.extend_from_slice(&offsets[range]);
+        });
+        self.uploaded = 0;
+    }
+}
+
+/// Textures a blur runs in, at `1 / 2^level` of the target's resolution.
+struct Level {
+    /// Content to blur. On the full resolution level, that's what was
+    /// drawn or copied into it, on the others it's downsampled from the
+    /// level above.
+    source_view: wgpu::TextureView,
+    /// Output of the horizontal pass, read by the vertical one.
+    horizontal_view: wgpu::TextureView,
+    /// Reads the level above, `None` on the full resolution level.
+    downsample_bind_group: Option<wgpu::BindGroup>,
+    width: u32,
+    height: u32,
+}
+
+/// Textures, kernels and bind groups of a gaussian blur, shared by the
+/// texture and backdrop blurs.
+///
+/// Small sigmas are blurred at full resolution. Larger ones are blurred on a
+/// copy downsampled by [`downsample`](Self::downsample), see [`blur_level`].
+/// A blur reads the source of its level through the horizontal bind group
+/// and writes the level's horizontal texture, which the vertical bind group
+/// reads in turn.
+pub(crate) struct BlurLevels {
+    levels: Vec<Level>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    downsample_bind_group_layout: wgpu::BindGroupLayout,
+    downsample_pipeline: wgpu::RenderPipeline,
+    /// `[horizontal, vertical]` bind groups of each level.
+    bind_groups: Vec<[wgpu::BindGroup; 2]>,
+    kernels: Kernels,
+    storage_buffers: StorageBuffers,
+    sampler: wgpu::Sampler,
+}
+
+impl BlurLevels {
+    /// WGSL that shaders append to their own, providing the bindings of
+    /// [`bind_group_layout`](Self::bind_group_layout) and `blur`.
+    pub const SHADER: &'static str = include_str!("shader.wgsl");
+
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+
+        let downsample_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("downsample_bind_group_layout"),
+            });
+
+        let downsample_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("downsample_pipeline_layout"),
+                bind_group_layouts: &[&downsample_bind_group_layout],
+                immediate_size: 0,
+            });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("downsample_shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::SHADER.into()),
+        });
+
+        let downsample_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("blur downsample pipeline"),
+            layout: Some(&downsample_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_fullscreen"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_downsample"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::default(),
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        // Kernel offsets fall between two texels, which linear filtering
+        // weighs in a single sample
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("blur_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let levels = create_levels(
+            device,
+            &downsample_bind_group_layout,
+            &sampler,
+            width,
+            height,
+            format,
+        );
+
+        let storage_buffers = (
+            buffers::StorageBuffer::with_capacity(device, INITIAL_KERNEL_CAPACITY),
+            buffers::StorageBuffer::with_capacity(device, INITIAL_KERNEL_CAPACITY),
+        );
+        let bind_groups = create_bind_groups(
+            device,
+            &bind_group_layout,
+            &sampler,
+            &levels,
+            &storage_buffers,
+        );
+
+        Self {
+            levels,
+            bind_group_layout,
+            downsample_bind_group_layout,
+            downsample_pipeline,
+            bind_groups,
+            kernels: Kernels::default(),
+            storage_buffers,
+            sampler,
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) {
+        self.levels = create_levels(
+            device,
+            &self.downsample_bind_group_layout,
+            &self.sampler,
+            width,
+            height,
+            format,
+        );
+        self.bind_groups = create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.levels,
+            &self.storage_buffers,
+        );
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// `[horizontal, vertical]` bind groups of `level`.
+    pub fn bind_groups(&self, level: u32) -> &[wgpu::BindGroup; 2] {
+        &self.bind_groups[level as usize]
+    }
+
+    /// Content to blur at full resolution, which the other levels are
+    /// downsampled from.
+    pub fn source_view(&self) -> &wgpu::TextureView {
+        &self.levels[0].source_view
+    }
+
+    /// Output of the horizontal pass at `level`.
+    pub fn horizontal_view(&self, level: u32) -> &wgpu::TextureView {
+        &self.levels[level as usize].horizontal_view
+    }
+
+    /// Builds and uploads the kernels of `sigmas` that are missing, for
+    /// [`kernel`](Self::kernel) to find.
+    pub fn prepare_kernels(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sigmas: impl IntoIterator<Item = f32>,
+    ) {
+        self.kernels.begin_frame();
+        self.kernels
+            .insert_missing(sigmas, self.storage_buffers.0.capacity());
+
+        let len = self.kernels.weights.len();
+        let (weights, _) = &self.storage_buffers;
+        if weights.capacity() < len {
+            // Grow geometrically, so that a few new sigmas per frame
+            // don't reallocate every time
+            let capacity = len.max(weights.capacity() * 2);
+            self.storage_buffers = (
+                buffers::StorageBuffer::with_capacity(device, capacity),
+                buffers::StorageBuffer::with_capacity(device, capacity),
+            );
+            self.bind_groups = create_bind_groups(
+                device,
+                &self.bind_group_layout,
+                &self.sampler,
+                &self.levels,
+                &self.storage_buffers,
+            );
+            self.kernels.uploaded = 0;
+        }
+
+        let start = self.kernels.uploaded;
+        if start < len {
+            let (weights, offsets) = &self.storage_buffers;
+            weights.write_at(queue, start, &self.kernels.weights[start..]);
+            offsets.write_at(queue, start, &self.kernels.offsets[start..]);
+            self.kernels.uploaded = len;
+        }
+    }
+
+    /// `[first, count]` of the weights and offsets of the kernel of `sigma`,
+    /// where no weights pass the texture through.
+    pub fn kernel(&mut self, sigma: f32) -> [u32; 2] {
+        self.kernels.get(sigma).unwrap_or_default()
+    }
+
+    /// Downsamples the `[left, top, right, bottom]` pixels of the full
+    /// resolution source into the levels up to `max_level`.
+    pub fn downsample(&self, encoder: &mut wgpu::CommandEncoder, max_level: u32, region: [f32; 4]) {
+        // Every halving can pull in a texel from outside of the region,
+        // which the padding keeps away from what's read
+        let padding = (2 << max_level) as f32;
+        let [left, top, right, bottom] = region;
+        let [left, top, right, bottom] = [
+            left - padding,
+            top - padding,
+            right + padding,
+            bottom + padding,
+        ];
+
+        (1..=max_level as usize).for_each(|i| {
+            let level = &self.levels[i];
+            let factor = (1 << i) as f32;
+            let x0 = ((left / factor).floor().max(0.) as u32).min(level.width);
+            let y0 = ((top / factor).floor().max(0.) as u32).min(level.height);
+            let x1 = ((right / factor).ceil().max(0.) as u32).min(level.width);
+            let y1 = ((bottom / factor).ceil().max(0.) as u32).min(level.height);
+
+            let mut downsample_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blur_downsample_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &level.source_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+
+            if x1 <= x0 || y1 <= y0 {
+                return;
+            }
+
+            downsample_pass.set_pipeline(&self.downsample_pipeline);
+            downsample_pass.set_bind_group(0, level.downsample_bind_group.as_ref(), &[]);
+            downsample_pass.set_scissor_rect(x0, y0, x1 - x0, y1 - y0);
+            downsample_pass.draw(0..3, 0..1);
+        });
+    }
+}
+
+/// Creates the `[horizontal, vertical]` bind groups of each level.
+fn create_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    levels: &[Level],
+    (weights, offsets): &StorageBuffers,
+) -> Vec<[wgpu::BindGroup; 2]> {
+    let bind_group = |label, view| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: weights.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: offsets.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some(label),
+        })
+    };
+
+    levels
+        .iter()
+        .map(|level| {
+            [
+                bind_group("horizontal_blur_bg", &level.source_view),
+                bind_group("vertical_blur_bg", &level.horizontal_view),
+            ]
+        })
+        .collect()
+}
+
+/// Creates the full resolution level and the downsampled ones below it.
+fn create_levels(
+    device: &wgpu::Device,
+    downsample_bind_group_layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Vec<Level> {
+    let create_view = |label, width: u32, height: u32| {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // Backdrops copy the target into the full resolution level
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            ..Default::default()
+        })
+    };
+
+    let mut levels: Vec<Level> = Vec::with_capacity(MAX_LEVEL as usize + 1);
+    (0..=MAX_LEVEL).for_each(|i| {
+        let (width, height) = ((width >> i).max(1), (height >> i).max(1));
+        let downsample_bind_group = levels.last().map(|above| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: downsample_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&above.source_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ],
+                label: Some("downsample_bg"),
+            })
+        });
+
+        levels.push(Level {
+            source_view: create_view("blur_source_texture", width, height),
+            horizontal_view: create_view("horizontal_blur_texture", width, height),
+            downsample_bind_group,
+            width,
+            height,
+        });
+    });
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_are_rounded() {
+        let mut kernels = Kernels::default();
+        kernels.begin_frame();
+        kernels.insert_missing([4., 4.01], 1024);
+
+        assert_eq!(kernels.kernels.len(), 1);
+        assert_eq!(kernels.get(4.01), kernels.get(3.99));
+        assert_eq!(kernels.get(0.01), Some([0, 0]));
+    }
+
+    #[test]
+    fn kernels_are_kept_until_full() {
+        let mut kernels = Kernels::default();
+        kernels.begin_frame();
+        kernels.insert_missing([2., 3.], 64);
+        let kept = kernels.get(2.).unwrap();
+        let unused = kernels.get(3.).unwrap();
+        kernels.uploaded = kernels.weights.len();
+
+        // A new sigma is appended without moving the others
+        kernels.begin_frame();
+        kernels.insert_missing([2., 4.], 64);
+        assert_eq!(kernels.get(2.), Some(kept));
+        assert_eq!(kernels.get(3.), Some(unused));
+        assert_eq!(kernels.get(4.).unwrap()[0], unused[0] + unused[1]);
+        assert!(kernels.uploaded > 0);
+
+        // Out of room, so the kernels not used this frame are dropped
+        kernels.begin_frame();
+        kernels.get(2.);
+        kernels.insert_missing([2., 12.], 64);
+        assert_eq!(kernels.get(3.), None);
+        assert_eq!(kernels.get(4.), None);
+        assert_eq!(kernels.get(2.), Some([0, kept[1]]));
+        assert_eq!(kernels.get(12.).unwrap()[0], kept[1]);
+        assert_eq!(kernels.uploaded, 0);
+    }
+}
@@ -0,0 +1,50 @@
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+@group(0) @binding(2)
+var<storage, read> weights: array<f32>;
+@group(0) @binding(3)
+var<storage, read> offsets: array<f32>;
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+// A single triangle covering the whole target
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+
+    return out;
+}
+
+// Averages the 2x2 texels of the level above that meet at this texel
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(t_diffuse, s_diffuse, in.tex_coords, 0.0);
+}
+
+// Blurs along `direction` with the `[first, count]` weights and offsets of
+// `kernel`, where no weights pass the texture through
+fn blur(tex_coords: vec2<f32>, kernel: vec2<u32>, direction: vec2<f32>, blur_color: vec4<f32>) -> vec4<f32> {
+    if kernel.y == 0 {
+        return textureSampleLevel(t_diffuse, s_diffuse, tex_coords, 0.0);
+    }
+
+    // Offsets are in texels of the level being read
+    let texel = direction / vec2<f32>(textureDimensions(t_diffuse));
+
+    var color: vec4<f32> = blur_color;
+    for (var i: u32 = kernel.x; i < kernel.x + kernel.y; i++) {
+        let sample_coord = tex_coords + texel * offsets[i];
+        color += textureSampleLevel(t_diffuse, s_diffuse, sample_coord, 0.0) * weights[i];
+    }
+
+    return color;
+}
@@ -1,3 +1,7 @@
+#[cfg(any(feature = "shape_renderer", feature = "texture_renderer"))]
+pub mod backdrop;
+#[cfg(any(feature = "shape_renderer", feature = "texture_renderer"))]
+mod blur;
 #[cfg(feature = "shape_renderer")]
 pub mod shape_renderer;
 #[cfg(feature = "text_renderer")]
@@ -1,6 +1,11 @@
+mod paint;
+
+use crate::backdrop::Backdrop;
 use crate::buffers;
 use crate::buffers::{DataDescription, GpuBuffer, instance::InstanceBuffer};
+use crate::clip::{ClipBuffer, ClipId, ClipStack};
 use crate::viewport;
+pub use paint::{GradientStop, Paint};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -13,6 +18,37 @@ pub struct ShapeInstance {
     pub border_color: [f32; 4],
     pub scale: f32,
     pub depth: f32,
+    pub shadow_offset: [f32; 2],
+    /// Blur radius of the shadow, as in CSS `box-shadow`.
+    pub shadow_blur: f32,
+    pub shadow_spread: f32,
+    pub shadow_color: [f32; 4],
+    /// Non-zero draws the shadow inside the shape, like CSS `inset`.
+    pub shadow_inset: u32,
+    /// Clips of the [`ClipStack`] passed to
+    /// [`ShapeRenderer::prepare_clips`] the shape and its shadow are confined to.
+    pub clip: ClipId,
+}
+
+impl Default for ShapeInstance {
+    fn default() -> Self {
+        Self {
+            rect_pos: [0., 0.],
+            rect_size: [0., 0.],
+            rect_color: [0., 0., 0., 0.],
+            border_radius: [0., 0., 0., 0.],
+            border_size: [0., 0., 0., 0.],
+            border_color: [0., 0., 0., 0.],
+            scale: 1.,
+            depth: 0.,
+            shadow_offset: [0., 0.],
+            shadow_blur: 0.,
+            shadow_spread: 0.,
+            shadow_color: [0., 0., 0., 0.],
+            shadow_inset: 0,
+            clip: ClipId::default(),
+        }
+    }
 }
 
 impl DataDescription for ShapeInstance {
@@ -27,16 +63,124 @@ impl DataDescription for ShapeInstance {
         6 => Float32x4,
         7 => Float32,
         8 => Float32,
+        9 => Float32x2,
+        10 => Float32,
+        11 => Float32,
+        12 => Float32x4,
+        13 => Uint32,
+        14 => Uint32x2,
     ];
 }
 
 impl buffers::instance::Instance for ShapeInstance {}
 
+/// A shape whose fill and border can be painted with gradients.
+#[derive(Debug, Clone)]
+pub struct Shape {
+    pub instance: ShapeInstance,
+    pub fill: Paint,
+    pub border: Paint,
+    /// Blurs what's behind the shape, as in CSS `backdrop-filter: blur()`.
+    /// Only takes effect through a [`BackdropRenderer`](crate::backdrop::BackdropRenderer),
+    /// e.g. in a [`Scene`](crate::scene::Scene).
+    ///
+    /// The blur isn't tinted on its own. The shape is drawn over it as
+    /// usual, so a translucent fill is what tints the glass.
+    pub backdrop_blur: f32,
+}
+
+impl From<ShapeInstance> for Shape {
+    fn from(instance: ShapeInstance) -> Self {
+        Self {
+            fill: Paint::Solid(instance.rect_color),
+            border: Paint::Solid(instance.border_color),
+            backdrop_blur: 0.,
+            instance,
+        }
+    }
+}
+
+impl Shape {
+    /// Region whose backdrop is blurred, which is the shape including its
+    /// border, or `None` without a backdrop blur.
+    pub fn backdrop(&self) -> Option<Backdrop> {
+        if self.backdrop_blur <= 0. {
+            return None;
+        }
+
+        let instance = &self.instance;
+        let [left, right, top, bottom] = instance.border_size;
+        let width = instance.rect_size[0] + left + right;
+        let height = instance.rect_size[1] + top + bottom;
+
+        // Same as the outer radii in the shader
+        let max_radius = width.min(height) * 0.5;
+        let [r0, r1, r2, r3] = instance.border_radius;
+        let bottom_right = (r0 + left + top).min(max_radius);
+        let top_right = (r1 + right + top).min(max_radius);
+        let bottom_left = (r2 + left + bottom).min(max_radius);
+        let top_left = (r3 + right + bottom).min(max_radius);
+
+        let scale = instance.scale;
+        Some(Backdrop {
+            rect: [
+                instance.rect_pos[0] * scale,
+                instance.rect_pos[1] * scale,
+                width * scale,
+                height * scale,
+            ],
+            radius: [top_left, top_right, bottom_right, bottom_left].map(|r| r * scale),
+            blur: self.backdrop_blur * scale,
+            depth: instance.depth,
+            clip: instance.clip,
+            ..Default::default()
+        })
+    }
+}
+
+/// [`ShapeInstance`] plus the gradients it's painted with, as
+/// `[fill, border]` indices into the gradient storage buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct PaintedInstance {
+    instance: ShapeInstance,
+    paint: [u32; 2],
+}
+
+impl DataDescription for PaintedInstance {
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+
+    const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32,
+        8 => Float32,
+        9 => Float32x2,
+        10 => Float32,
+        11 => Float32,
+        12 => Float32x4,
+        13 => Uint32,
+        14 => Uint32x2,
+        15 => Uint32x2,
+    ];
+}
+
+impl buffers::instance::Instance for PaintedInstance {}
+
 pub struct ShapeRenderer {
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: buffers::VertexBuffer,
     index_buffer: buffers::IndexBuffer,
-    instance_buffer: InstanceBuffer<ShapeInstance>,
+    instance_buffer: InstanceBuffer<PaintedInstance>,
+    gradient_bind_group_layout: wgpu::BindGroupLayout,
+    gradient_bind_group: wgpu::BindGroup,
+    gradient_buffers: paint::GradientBuffers,
+    gradients: paint::Gradients,
+    clips: ClipBuffer,
 }
 
 impl ShapeRenderer {
@@ -56,21 +200,49 @@ impl ShapeRenderer {
                 label: Some("uniform_bind_group_layout"),
             });
 
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let gradient_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[storage_entry(0), storage_entry(1), storage_entry(2)],
+                label: Some("gradient_bind_group_layout"),
+            });
+
+        let clips = ClipBuffer::new(device);
+
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&uniform_bind_group_layout],
+                bind_group_layouts: &[
+                    &uniform_bind_group_layout,
+                    &gradient_bind_group_layout,
+                    clips.bind_group_layout(),
+                ],
                 immediate_size: 0,
             });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shader.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shape_shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{}{}", include_str!("shader.wgsl"), ClipBuffer::SHADER).into(),
+            ),
+        });
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
             layout: Some(&render_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
-                buffers: &[buffers::Vertex::desc(), ShapeInstance::desc()],
+                buffers: &[buffers::Vertex::desc(), PaintedInstance::desc()],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
@@ -130,43 +302,104 @@ impl ShapeRenderer {
 
         let instance_buffer = InstanceBuffer::new(device, &[]);
 
+        let gradients = paint::Gradients::default();
+        let (gradient_buffers, gradient_bind_group) =
+            gradients.bind_group(device, &gradient_bind_group_layout);
+
         Self {
             render_pipeline,
             vertex_buffer,
             index_buffer,
             instance_buffer,
+            gradient_bind_group_layout,
+            gradient_bind_group,
+            gradient_buffers,
+            gradients,
+            clips,
         }
     }
 
+    /// Uploads the clips that [`ShapeInstance::clip`] refers to.
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.clips.prepare(device, queue, clips);
+    }
+
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         instances: &[ShapeInstance],
     ) {
+        let shapes = instances
+            .iter()
+            .map(|instance| Shape::from(*instance))
+            .collect::<Vec<_>>();
+
+        self.prepare_shapes(device, queue, &shapes);
+    }
+
+    /// Like [`prepare`](Self::prepare), but fills and borders can be
+    /// painted with gradients.
+    pub fn prepare_shapes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, shapes: &[Shape]) {
+        let mut gradients = paint::Gradients::default();
+
+        let instances = shapes
+            .iter()
+            .map(|shape| {
+                let mut instance = shape.instance;
+                if let Paint::Solid(color) = shape.fill {
+                    instance.rect_color = color;
+                }
+                if let Paint::Solid(color) = shape.border {
+                    instance.border_color = color;
+                }
+
+                PaintedInstance {
+                    instance,
+                    paint: [gradients.push(&shape.fill), gradients.push(&shape.border)],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Gradients of static UIs rarely change, so only re-upload them when they do
+        if gradients != self.gradients {
+            (self.gradient_buffers, self.gradient_bind_group) =
+                gradients.bind_group(device, &self.gradient_bind_group_layout);
+            self.gradients = gradients;
+        }
+
         if instances.is_empty() {
+            self.instance_buffer.write(queue, &[]);
             return;
         }
 
-        let needed_buffer_size = std::mem::size_of_val(instances);
+        let needed_buffer_size = std::mem::size_of_val(instances.as_slice());
 
         if self.instance_buffer.size() < needed_buffer_size as u32 {
             self.instance_buffer = InstanceBuffer::with_size(device, needed_buffer_size as u64);
         }
 
-        self.instance_buffer.write(queue, instances);
+        self.instance_buffer.write(queue, &instances);
     }
 
     pub fn render(&self, render_pass: &mut wgpu::RenderPass<'_>, viewport: &viewport::Viewport) {
+        self.render_range(render_pass, viewport, 0..self.instance_buffer.size());
+    }
+
+    /// Draws only the prepared shapes in `instances`.
+    pub(crate) fn render_range(
+        &self,
+        render_pass: &mut wgpu::RenderPass<'_>,
+        viewport: &viewport::Viewport,
+        instances: std::ops::Range<u32>,
+    ) {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &viewport.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.gradient_bind_group, &[]);
+        render_pass.set_bind_group(2, self.clips.bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(
-            0..self.index_buffer.size(),
-            0,
-            0..self.instance_buffer.size(),
-        );
+        render_pass.draw_indexed(0..self.index_buffer.size(), 0, instances);
     }
 }
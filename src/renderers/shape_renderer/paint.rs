@@ -0,0 +1,164 @@
+use crate::buffers;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop {
+    /// Position of the stop along the gradient, from 0 to 1.
+    pub offset: f32,
+    pub color: [f32; 4],
+}
+
+impl GradientStop {
+    pub fn new(offset: f32, color: [f32; 4]) -> Self {
+        Self { offset, color }
+    }
+}
+
+/// How the fill or the border of a shape is colored.
+///
+/// Gradients span the box they paint: the fill spans the shape without its
+/// border, and the border spans the whole shape. Stops are interpolated in
+/// sRGB, like in CSS.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paint {
+    Solid([f32; 4]),
+    /// Like CSS `linear-gradient`, `angle` is in degrees with 0 pointing up
+    /// and 90 pointing right.
+    Linear {
+        angle: f32,
+        stops: Vec<GradientStop>,
+    },
+    /// Elliptical gradient around `center` with radii `radius`, both as
+    /// fractions of the painted box.
+    Radial {
+        center: [f32; 2],
+        radius: [f32; 2],
+        stops: Vec<GradientStop>,
+    },
+    /// Like CSS `conic-gradient`, sweeps clockwise around `center` starting
+    /// at `angle` degrees from the top. `center` is a fraction of the box.
+    Conic {
+        center: [f32; 2],
+        angle: f32,
+        stops: Vec<GradientStop>,
+    },
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct GpuGradient {
+    kind: u32,
+    first_stop: u32,
+    stop_count: u32,
+    _pad: u32,
+    params: [f32; 4],
+}
+
+const LINEAR: u32 = 1;
+const RADIAL: u32 = 2;
+const CONIC: u32 = 3;
+
+/// Gradients of one frame, flattened into what the shader reads from its
+/// storage buffers.
+#[derive(Default, PartialEq)]
+pub(super) struct Gradients {
+    gradients: Vec<GpuGradient>,
+    colors: Vec<[f32; 4]>,
+    offsets: Vec<f32>,
+}
+
+impl Gradients {
+    /// Adds `paint` and returns the index the shader knows it by, where 0
+    /// means a solid color that's carried by the instance itself.
+    pub fn push(&mut self, paint: &Paint) -> u32 {
+        let (kind, params, stops) = match paint {
+            Paint::Solid(_) => return 0,
+            Paint::Linear { angle, stops } => (LINEAR, [angle.to_radians(), 0., 0., 0.], stops),
+            Paint::Radial {
+                center,
+                radius,
+                stops,
+            } => (RADIAL, [center[0], center[1], radius[0], radius[1]], stops),
+            Paint::Conic {
+                center,
+                angle,
+                stops,
+            } => (CONIC, [center[0], center[1], angle.to_radians(), 0.], stops),
+        };
+
+        self.gradients.push(GpuGradient {
+            kind,
+            first_stop: self.colors.len() as u32,
+            stop_count: stops.len() as u32,
+            _pad: 0,
+            params,
+        });
+        stops.iter().for_each(|stop| {
+            self.colors.push(stop.color);
+            self.offsets.push(stop.offset);
+        });
+
+        self.gradients.len() as u32
+    }
+
+    /// Uploads the gradients and binds them according to `layout`.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> (GradientBuffers, wgpu::BindGroup) {
+        // Storage buffers can't be empty, so keep a dummy element around
+        let gradients = if self.gradients.is_empty() {
+            buffers::StorageBuffer::new(
+                device,
+                &[GpuGradient {
+                    kind: 0,
+                    first_stop: 0,
+                    stop_count: 0,
+                    _pad: 0,
+                    params: [0.; 4],
+                }],
+            )
+        } else {
+            buffers::StorageBuffer::new(device, &self.gradients)
+        };
+
+        let colors = if self.colors.is_empty() {
+            buffers::StorageBuffer::new(device, &[[0.0f32; 4]])
+        } else {
+            buffers::StorageBuffer::new(device, &self.colors)
+        };
+
+        let offsets = if self.offsets.is_empty() {
+            buffers::StorageBuffer::new(device, &[0.0f32])
+        } else {
+            buffers::StorageBuffer::new(device, &self.offsets)
+        };
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: gradients.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: colors.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: offsets.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("gradient_bind_group"),
+        });
+
+        ((gradients, colors, offsets), bind_group)
+    }
+}
+
+pub(super) type GradientBuffers = (
+    buffers::StorageBuffer<GpuGradient>,
+    buffers::StorageBuffer<[f32; 4]>,
+    buffers::StorageBuffer<f32>,
+);
@@ -5,6 +5,25 @@ struct Params {
 @group(0) @binding(0)
 var<uniform> params: Params;
 
+struct Gradient {
+    kind: u32,
+    first_stop: u32,
+    stop_count: u32,
+    _pad: u32,
+    // linear: [angle, -, -, -], radial: [center, radius], conic: [center, angle, -]
+    params: vec4<f32>,
+};
+@group(1) @binding(0)
+var<storage, read> gradients: array<Gradient>;
+@group(1) @binding(1)
+var<storage, read> stop_colors: array<vec4<f32>>;
+@group(1) @binding(2)
+var<storage, read> stop_offsets: array<f32>;
+
+const LINEAR: u32 = 1u;
+const RADIAL: u32 = 2u;
+const CONIC: u32 = 3u;
+
 struct VertexInput {
     @location(0) position: vec2<f32>,
 };
@@ -20,6 +39,11 @@ struct VertexOutput {
     @location(6) border_color: vec4<f32>,
     @location(7) scale: f32,
     @location(8) depth: f32,
+    @location(9) shadow: vec4<f32>,  // [offset_x, offset_y, blur, spread]
+    @location(10) shadow_color: vec4<f32>,
+    @location(11) @interpolate(flat) shadow_inset: u32,
+    @location(12) @interpolate(flat) paint: vec2<u32>,  // [fill, border]
+    @location(13) @interpolate(flat) clip: vec2<u32>,  // [first, count] of the clip rects
 };
 
 struct InstanceInput {
@@ -31,6 +55,24 @@ struct InstanceInput {
     @location(6) border_color: vec4<f32>,
     @location(7) scale: f32,
     @location(8) depth: f32,
+    @location(9) shadow_offset: vec2<f32>,
+    @location(10) shadow_blur: f32,
+    @location(11) shadow_spread: f32,
+    @location(12) shadow_color: vec4<f32>,
+    @location(13) shadow_inset: u32,
+    @location(14) clip: vec2<u32>,
+    @location(15) paint: vec2<u32>,
+}
+
+// How far an outer shadow reaches past each edge of the shape as [left, top, right, bottom]
+fn shadow_outsets(instance: InstanceInput) -> vec4<f32> {
+    if instance.shadow_inset != 0u || instance.shadow_color.a <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+
+    // The gaussian is negligible past three sigmas, sigma being half the blur radius
+    let reach = instance.shadow_blur * 1.5 + instance.shadow_spread;
+    return max(vec4<f32>(reach - instance.shadow_offset, reach + instance.shadow_offset), vec4<f32>(0.0));
 }
 
 @vertex
@@ -40,7 +82,9 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
 
-    let pixel_pos = model.position * (instance.rect_size + vec2<f32>(instance.border_size[0], instance.border_size[2]) + vec2<f32>(instance.border_size[1], instance.border_size[3])) * instance.scale + instance.rect_pos * instance.scale;
+    let outer_size = instance.rect_size + vec2<f32>(instance.border_size[0], instance.border_size[2]) + vec2<f32>(instance.border_size[1], instance.border_size[3]);
+    let outsets = shadow_outsets(instance);
+    let pixel_pos = (mix(-outsets.xy, outer_size + outsets.zw, model.position) + instance.rect_pos) * instance.scale;
 
     let resolution = vec2<f32>(params.screen_resolution);
     let ndc = (pixel_pos / resolution) * 2.0 - vec2<f32>(1.0, 1.0);
@@ -68,6 +112,11 @@ fn vs_main(
     out.border_color = instance.border_color;
     out.scale = instance.scale;
     out.depth = instance.depth;
+    out.shadow = vec4<f32>(instance.shadow_offset, instance.shadow_blur, instance.shadow_spread) * instance.scale;
+    out.shadow_color = instance.shadow_color;
+    out.shadow_inset = instance.shadow_inset;
+    out.paint = instance.paint;
+    out.clip = instance.clip;
 
     return out;
 }
@@ -82,6 +131,120 @@ fn sdf_rounded_rect(p: vec2<f32>, b: vec2<f32>, r: vec4<f32>) -> f32 {
     return min(max(q.x, q.y), 0.0) + length(max(q, vec2<f32>(0.0))) - x;
 }
 
+fn corner_radius(p: vec2<f32>, r: vec4<f32>) -> f32 {
+    let x = select(r.z, r.x, p.x > 0.0);
+    let y = select(r.w, r.y, p.x > 0.0);
+    return select(y, x, p.y > 0.0);
+}
+
+// Abramowitz and Stegun approximation, max error 5e-4
+fn erf(x: vec2<f32>) -> vec2<f32> {
+    let s = sign(x);
+    let a = abs(x);
+    var r = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+    r = r * r;
+    return s - s / (r * r);
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    let pi = 3.141592653589793;
+    return exp(-(x * x) / (2.0 * sigma * sigma)) / (sqrt(2.0 * pi) * sigma);
+}
+
+// Blurred coverage of one row of a rounded box, integrated along x in closed form
+fn rounded_box_shadow_x(x: f32, y: f32, sigma: f32, corner: f32, half_size: vec2<f32>) -> f32 {
+    let delta = min(half_size.y - corner - abs(y), 0.0);
+    let curved = half_size.x - corner + sqrt(max(0.0, corner * corner - delta * delta));
+    let integral = 0.5 + 0.5 * erf((x + vec2<f32>(-curved, curved)) * (sqrt(0.5) / sigma));
+    return integral.y - integral.x;
+}
+
+// Coverage of a rounded box blurred with a gaussian. The x axis is solved
+// analytically and the y axis is sampled, as in Evan Wallace's
+// "Fast Rounded Rectangle Shadows".
+fn rounded_box_shadow(p: vec2<f32>, half_size: vec2<f32>, radius: vec4<f32>, sigma: f32) -> f32 {
+    let corner = min(corner_radius(p, radius), min(half_size.x, half_size.y));
+
+    // Derivatives have to be taken in uniform control flow
+    let dist = sdf_rounded_rect(p, half_size, vec4<f32>(corner));
+    let aa = fwidth(dist);
+    if sigma <= 0.0 {
+        return smoothstep(-aa, aa, -dist);
+    }
+
+    let low = p.y - half_size.y;
+    let high = p.y + half_size.y;
+    let start = clamp(-3.0 * sigma, low, high);
+    let end = clamp(3.0 * sigma, low, high);
+
+    let step = (end - start) / 4.0;
+    var y = start + step * 0.5;
+    var value = 0.0;
+    for (var i = 0; i < 4; i++) {
+        value += rounded_box_shadow_x(p.x, p.y - y, sigma, corner, half_size) * gaussian(y, sigma) * step;
+        y += step;
+    }
+
+    return value;
+}
+
+// Color of the gradient at position t between its stops
+fn sample_stops(gradient: Gradient, t: f32) -> vec4<f32> {
+    let first = gradient.first_stop;
+    let last = first + gradient.stop_count - 1u;
+
+    if t <= stop_offsets[first] {
+        return stop_colors[first];
+    }
+
+    for (var i = first + 1u; i <= last; i++) {
+        if t <= stop_offsets[i] {
+            let start = stop_offsets[i - 1u];
+            let f = clamp((t - start) / max(stop_offsets[i] - start, 1e-6), 0.0, 1.0);
+            return mix(stop_colors[i - 1u], stop_colors[i], f);
+        }
+    }
+
+    return stop_colors[last];
+}
+
+// Color of paint `index` at position p relative to the top-left corner of the
+// painted box, or `solid` for solid paints
+fn paint_color(index: u32, solid: vec4<f32>, p: vec2<f32>, size: vec2<f32>) -> vec4<f32> {
+    if index == 0u {
+        return solid;
+    }
+
+    let gradient = gradients[index - 1u];
+    if gradient.stop_count == 0u {
+        return vec4<f32>(0.0);
+    }
+
+    let pi = 3.141592653589793;
+    var t = 0.0;
+    switch gradient.kind {
+        case LINEAR: {
+            // Like CSS, the gradient line passes through the center and is
+            // long enough for the corners to get the first and last stops
+            let angle = gradient.params.x;
+            let direction = vec2<f32>(sin(angle), -cos(angle));
+            let line_length = abs(size.x * sin(angle)) + abs(size.y * cos(angle));
+            t = dot(p - size * 0.5, direction) / max(line_length, 1e-6) + 0.5;
+        }
+        case RADIAL: {
+            let radius = max(gradient.params.zw, vec2<f32>(1e-6));
+            t = length((p / max(size, vec2<f32>(1e-6)) - gradient.params.xy) / radius);
+        }
+        case CONIC: {
+            let d = p - gradient.params.xy * size;
+            t = fract((atan2(d.x, -d.y) - gradient.params.z) / (2.0 * pi));
+        }
+        default: {}
+    }
+
+    return sample_stops(gradient, t);
+}
+
 struct FragmentOutput {
     @location(0) color: vec4<f32>,
     @builtin(frag_depth) depth: f32,
@@ -115,18 +278,46 @@ fn fs_main(in: VertexOutput) -> FragmentOutput {
     let outer_alpha = smoothstep(-outer_aa, outer_aa, -outer_dist);
     let border_alpha = outer_alpha - inner_alpha;
 
-    if outer_alpha < 0.001 {
+    // === SHADOW ===
+    // Like CSS box-shadow, outer shadows are cast by the border box and
+    // inset shadows by the padding box
+    let shadow_offset = in.shadow.xy;
+    let shadow_sigma = in.shadow.z * 0.5;
+    let shadow_spread = in.shadow.w;
+    let inset = in.shadow_inset != 0u;
+
+    let caster_center = select(outer_center, inner_center, inset) + shadow_offset;
+    let caster_half_size = select(outer_size, in.rect_size, inset) * 0.5 + select(shadow_spread, -shadow_spread, inset);
+    let caster_radius = max(in.border_radius + select(shadow_spread, -shadow_spread, inset), vec4<f32>(0.0));
+    let caster_coverage = rounded_box_shadow(in.uv - caster_center, max(caster_half_size, vec2<f32>(0.0)), caster_radius, shadow_sigma);
+
+    // An inset shadow darkens what the shifted box doesn't cover, an outer
+    // shadow is only visible outside of the shape
+    let shadow_alpha = select(caster_coverage * (1.0 - outer_alpha), (1.0 - caster_coverage) * inner_alpha, inset) * in.shadow_color.a;
+    let shadow_color = vec4<f32>(srgb_to_linear(in.shadow_color.rgb), 1.0) * shadow_alpha;
+
+    let clip = clip_coverage(in.clip_position.xy, in.clip);
+    if (outer_alpha < 0.001 && shadow_alpha < 0.001) || clip < 0.001 {
         discard;
     }
 
-    let inner_rgb = srgb_to_linear(in.rect_color.rgb);
-    let inner_color = vec4<f32>(inner_rgb * in.rect_color.a, in.rect_color.a) * inner_alpha;
+    let fill = paint_color(in.paint.x, in.rect_color, in.uv - in.rect_pos, in.rect_size);
+    let inner_rgb = srgb_to_linear(fill.rgb);
+    var inner_color = vec4<f32>(inner_rgb * fill.a, fill.a) * inner_alpha;
 
-    let border_rgb = srgb_to_linear(in.border_color.rgb);
-    let border_color = vec4<f32>(border_rgb * in.border_color.a, in.border_color.a) * border_alpha;
+    let border = paint_color(in.paint.y, in.border_color, in.uv - (outer_center - outer_size / 2.0), outer_size);
+    let border_rgb = srgb_to_linear(border.rgb);
+    let border_color = vec4<f32>(border_rgb * border.a, border.a) * border_alpha;
 
     var out: FragmentOutput;
-    out.color = inner_color + border_color;
+    if inset {
+        // Drawn over the background, but under the border
+        inner_color = shadow_color + inner_color * (1.0 - shadow_color.a);
+        out.color = inner_color + border_color;
+    } else {
+        out.color = inner_color + border_color + shadow_color;
+    }
+    out.color *= clip;
     out.depth = in.clip_position.z / in.clip_position.w;
     return out;
 }
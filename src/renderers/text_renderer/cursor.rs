@@ -0,0 +1,99 @@
+use super::TextArea;
+use glyphon::{Affinity, Cursor};
+
+impl TextArea<'_> {
+    /// Cursor at the glyph closest to `x` and `y` in pixels of the target,
+    /// e.g. where the pointer was pressed.
+    pub fn hit(&self, x: f32, y: f32) -> Option<Cursor> {
+        self.buffer
+            .hit((x - self.left) / self.scale, (y - self.top) / self.scale)
+    }
+
+    /// Byte index of `cursor` in the text the buffer was set to.
+    pub fn index(&self, cursor: Cursor) -> usize {
+        self.buffer.lines[..cursor.line.min(self.buffer.lines.len())]
+            .iter()
+            .map(|line| line.text().len() + line.ending().as_str().len())
+            .sum::<usize>()
+            + cursor.index
+    }
+
+    /// `[left, top, width, height]` of a one pixel wide caret at `cursor`, or
+    /// `None` if its line isn't laid out.
+    pub fn caret(&self, cursor: Cursor) -> Option<[f32; 4]> {
+        let runs = self
+            .buffer
+            .layout_runs()
+            .filter(|run| run.line_i == cursor.line)
+            .collect::<Vec<_>>();
+
+        // At the end of a wrapped line, the cursor is also at the start of
+        // the next one, and its affinity picks between them
+        let other = Cursor {
+            affinity: match cursor.affinity {
+                Affinity::Before => Affinity::After,
+                Affinity::After => Affinity::Before,
+            },
+            ..cursor
+        };
+        let (run, x) = [cursor, other]
+            .into_iter()
+            .find_map(|cursor| {
+                runs.iter().find_map(|run| {
+                    let (x, _) = run.highlight(cursor, cursor)?;
+                    Some((run, x))
+                })
+            })
+            .or_else(|| Some((runs.iter().find(|run| run.glyphs.is_empty())?, 0.)))?;
+
+        Some([
+            self.left + x * self.scale,
+            self.top + run.line_top * self.scale,
+            self.scale,
+            run.line_height * self.scale,
+        ])
+    }
+
+    /// `[left, top, width, height]` of the text between `start` and `end`,
+    /// one rectangle per line.
+    pub fn selection(&self, start: Cursor, end: Cursor) -> Vec<[f32; 4]> {
+        let (start, end) = (start.min(end), start.max(end));
+
+        self.buffer
+            .layout_runs()
+            .filter_map(|run| {
+                let (x, width) = run.highlight(start, end)?;
+                Some([
+                    self.left + x * self.scale,
+                    self.top + run.line_top * self.scale,
+                    width * self.scale,
+                    run.line_height * self.scale,
+                ])
+            })
+            .filter(|[_, _, width, _]| *width > 0.)
+            .collect()
+    }
+
+    /// Shapes highlighting the text between `start` and `end` in `color`,
+    /// [`BEHIND_TEXT_DEPTH`](super::BEHIND_TEXT_DEPTH) behind the text.
+    #[cfg(feature = "shape_renderer")]
+    pub fn selection_shapes(
+        &self,
+        start: Cursor,
+        end: Cursor,
+        color: [f32; 4],
+    ) -> Vec<crate::shape_renderer::ShapeInstance> {
+        self.selection(start, end)
+            .into_iter()
+            .map(
+                |[left, top, width, height]| crate::shape_renderer::ShapeInstance {
+                    rect_pos: [left, top],
+                    rect_size: [width, height],
+                    rect_color: color,
+                    depth: self.depth + super::BEHIND_TEXT_DEPTH,
+                    ..Default::default()
+                },
+            )
+            .collect()
+    }
+}
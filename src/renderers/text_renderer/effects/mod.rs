@@ -0,0 +1,752 @@
+use super::{BEHIND_TEXT_DEPTH, TextArea};
+use crate::buffers::{self, DataDescription, GpuBuffer, instance::InstanceBuffer};
+use crate::clip::{ClipBuffer, ClipId, ClipStack};
+use crate::viewport::Resolution;
+use std::ops::RangeInclusive;
+
+/// Drop shadow behind the glyphs, like CSS `text-shadow`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextShadow {
+    pub offset: [f32; 2],
+    /// Blur radius of the shadow, as in CSS `text-shadow`.
+    pub blur: f32,
+    pub color: glyphon::Color,
+}
+
+/// Stroke around the outside of the glyphs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextOutline {
+    /// Width in pixels, of which up to 8 are drawn exactly.
+    pub width: f32,
+    pub color: glyphon::Color,
+}
+
+/// Soft light around the glyphs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextGlow {
+    /// Blur radius of the glow, as in CSS `text-shadow`.
+    pub radius: f32,
+    pub color: glyphon::Color,
+}
+
+/// Effects drawn behind the glyphs of a [`TextArea`], e.g. to keep text
+/// readable over images. Sizes are in pixels before the area's scale.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextEffects {
+    pub shadow: Option<TextShadow>,
+    pub outline: Option<TextOutline>,
+    pub glow: Option<TextGlow>,
+}
+
+impl TextEffects {
+    pub fn is_empty(&self) -> bool {
+        self.shadow.is_none() && self.outline.is_none() && self.glow.is_none()
+    }
+
+    /// Pixels the effects reach past the glyphs. The gaussian is negligible
+    /// past three sigmas.
+    fn reach(&self) -> f32 {
+        let shadow = self.shadow.map_or(0., |shadow| {
+            shadow.offset[0].abs().max(shadow.offset[1].abs()) + shadow.blur * 1.5
+        });
+        let outline = self.outline.map_or(0., |outline| outline.width);
+        let glow = self.glow.map_or(0., |glow| glow.radius * 1.5);
+
+        shadow.max(outline).max(glow).ceil() + 1.
+    }
+}
+
+/// Holds the horizontal pass's output, which isn't color.
+const HORIZONTAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+fn color(color: glyphon::Color) -> [f32; 4] {
+    [color.r(), color.g(), color.b(), color.a()].map(|c| c as f32 / 255.)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct EffectsInstance {
+    rect: [f32; 4],
+    depth: f32,
+    shadow: [f32; 4], // [offset_x, offset_y, sigma, -]
+    shadow_color: [f32; 4],
+    outline_color: [f32; 4],
+    glow_color: [f32; 4],
+    widths: [f32; 2], // [outline width, glow sigma]
+    glyphs: [f32; 4], // [left, top, right, bottom]
+    clip: ClipId,
+    /// Whether the glyphs are drawn from the intermediate too, which is
+    /// how clipped text gets its rounded corners.
+    text: u32,
+    /// `[left, top, right, bottom]` whole pixels of the target the
+    /// horizontal pass covers.
+    region: [f32; 4],
+    /// Top left of the region in the horizontal texture, where every area
+    /// gets its own space.
+    scratch: [u32; 2],
+}
+
+impl DataDescription for EffectsInstance {
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+
+    const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        1 => Float32x4,
+        2 => Float32,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x2,
+        8 => Float32x4,
+        9 => Uint32x2,
+        10 => Uint32,
+        11 => Float32x4,
+        12 => Uint32x2,
+    ];
+}
+
+impl buffers::instance::Instance for EffectsInstance {}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    resolution: [u32; 2],
+    layers: u32,
+    _pad: u32,
+}
+
+/// Textures the effects are drawn through, at the size of the target.
+struct Intermediate {
+    /// The glyphs of the areas, at the same pixels as in the target.
+    text_view: wgpu::TextureView,
+    /// Output of the horizontal pass, with room for `layers` times the
+    /// target stacked on top of each other.
+    horizontal_view: wgpu::TextureView,
+    layers: u32,
+    /// Reads the glyphs, for the horizontal pass.
+    horizontal_bind_group: wgpu::BindGroup,
+    /// Reads the glyphs and the horizontal pass, for compositing.
+    bind_group: wgpu::BindGroup,
+}
+
+/// Draws the text of areas with effects or clips into an intermediate
+/// texture, and composites the effects from its coverage behind the text.
+/// The glyphs of clipped areas are composited from it as well, masked by
+/// their clips.
+///
+/// Blurs and the outline's dilation are separable: a horizontal pass writes
+/// them for every area into its own region of a scratch texture, and the
+/// vertical pass composites them.
+pub(crate) struct EffectsRenderer {
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    horizontal_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    horizontal_bind_group_layout: wgpu::BindGroupLayout,
+    params: Params,
+    params_buffer: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
+    vertex_buffer: buffers::VertexBuffer,
+    index_buffer: buffers::IndexBuffer,
+    instance_buffer: InstanceBuffer<EffectsInstance>,
+    // Depths of the areas of the instances, back to front
+    depths: Vec<f32>,
+    /// Created once effects are drawn.
+    intermediate: Option<Intermediate>,
+    text: glyphon::TextRenderer,
+    clips: ClipBuffer,
+}
+
+impl EffectsRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        atlas: &mut glyphon::TextAtlas,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[texture_entry(0), texture_entry(1)],
+            label: Some("text_effects_bind_group_layout"),
+        });
+        let horizontal_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[texture_entry(0)],
+                label: Some("text_effects_horizontal_bind_group_layout"),
+            });
+
+        let params_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("text_effects_params_bind_group_layout"),
+            });
+
+        let params = Params {
+            resolution: [0, 0],
+            layers: 0,
+            _pad: 0,
+        };
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("text_effects_params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &params_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+            label: Some("text_effects_params_bind_group"),
+        });
+
+        let clips = ClipBuffer::new(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("text_effects_pipeline_layout"),
+            bind_group_layouts: &[
+                &bind_group_layout,
+                &params_bind_group_layout,
+                clips.bind_group_layout(),
+            ],
+            immediate_size: 0,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("text_effects_shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{}{}", include_str!("shader.wgsl"), ClipBuffer::SHADER).into(),
+            ),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("text_effects_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[buffers::Vertex::desc(), EffectsInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            cache: None,
+            multiview_mask: None,
+        });
+
+        let horizontal_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("text_effects_horizontal_pipeline_layout"),
+                bind_group_layouts: &[&horizontal_bind_group_layout, &params_bind_group_layout],
+                immediate_size: 0,
+            });
+        let horizontal_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("text_effects_horizontal_pipeline"),
+            layout: Some(&horizontal_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_horizontal"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[buffers::Vertex::desc(), EffectsInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_horizontal"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HORIZONTAL_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            cache: None,
+            multiview_mask: None,
+        });
+
+        let vertex_buffer = buffers::VertexBuffer::new(
+            device,
+            &[
+                buffers::Vertex {
+                    position: [0.0, 0.0],
+                },
+                buffers::Vertex {
+                    position: [1.0, 0.0],
+                },
+                buffers::Vertex {
+                    position: [0.0, 1.0],
+                },
+                buffers::Vertex {
+                    position: [1.0, 1.0],
+                },
+            ],
+        );
+
+        let index_buffer = buffers::IndexBuffer::new(device, &[0, 1, 2, 3]);
+
+        Self {
+            format,
+            pipeline,
+            horizontal_pipeline,
+            bind_group_layout,
+            horizontal_bind_group_layout,
+            params,
+            params_buffer,
+            params_bind_group,
+            vertex_buffer,
+            index_buffer,
+            instance_buffer: InstanceBuffer::new(device, &[]),
+            depths: Vec::new(),
+            intermediate: None,
+            // Without depth, the intermediate only holds the coverage
+            text: glyphon::TextRenderer::new(
+                atlas,
+                device,
+                wgpu::MultisampleState::default(),
+                None,
+            ),
+            clips,
+        }
+    }
+
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.clips.prepare(device, queue, clips);
+    }
+
+    /// Returns the indices into `text` of the composited areas that didn't
+    /// fit the horizontal texture, which get no effects.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resolution: Resolution,
+        text: &[TextArea],
+        atlas: &mut glyphon::TextAtlas,
+        viewport: &glyphon::Viewport,
+        swash_cache: &mut glyphon::SwashCache,
+        font_system: &mut glyphon::FontSystem,
+    ) -> anyhow::Result<Vec<usize>> {
+        let size = [resolution.width.max(1), resolution.height.max(1)];
+        let mut instances = text
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.is_composited())
+            .filter_map(|(i, area)| Some((area.depth, i, Self::instance(area, size)?)))
+            .collect::<Vec<_>>();
+        // Stable, so that areas of one depth can be drawn as a range
+        instances.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+
+        // The horizontal texture grows up to the largest the device
+        // supports. Areas past that are left to be drawn without effects
+        // and clips.
+        let max_layers = device.limits().max_texture_dimension_2d / size[1];
+        let (layers, packed) = pack(&mut instances, size, max_layers);
+        let unpacked = instances
+            .drain(packed..)
+            .map(|(_, i, _)| i)
+            .collect::<Vec<_>>();
+
+        self.depths = instances.iter().map(|(depth, _, _)| *depth).collect();
+        if instances.is_empty() {
+            return Ok(unpacked);
+        }
+
+        if self.params.resolution != size
+            || self
+                .intermediate
+                .as_ref()
+                .is_none_or(|intermediate| intermediate.layers < layers)
+        {
+            self.params.resolution = size;
+            self.params.layers = layers;
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+            self.intermediate = Some(self.create_intermediate(device, size, layers));
+        }
+
+        let areas = instances
+            .iter()
+            .map(|(_, i, _)| &text[*i])
+            .collect::<Vec<_>>();
+        let instances = instances
+            .into_iter()
+            .map(|(_, _, instance)| instance)
+            .collect::<Vec<_>>();
+
+        let needed_buffer_size = std::mem::size_of_val(instances.as_slice()) as u64;
+        if self.instance_buffer.capacity() < needed_buffer_size {
+            self.instance_buffer = InstanceBuffer::with_size(
+                device,
+                needed_buffer_size.max(self.instance_buffer.capacity() * 2),
+            );
+        }
+        self.instance_buffer.write(queue, &instances);
+
+        self.text.prepare(
+            device,
+            queue,
+            font_system,
+            atlas,
+            viewport,
+            areas.iter().map(|area| area.to_glyphon()),
+            swash_cache,
+        )?;
+
+        Ok(unpacked)
+    }
+
+    /// Quad covering the glyphs of `area` and the reach of its effects on a
+    /// target of `size`, or `None` if that's outside of its bounds.
+    fn instance(area: &TextArea, [width, height]: [u32; 2]) -> Option<EffectsInstance> {
+        let (min, max) = area.buffer.layout_runs().fold(
+            ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]),
+            |(min, max), run| {
+                // Glyphs like italics can draw past their advance
+                let overhang = run.line_height / 4.;
+                let (left, right) =
+                    run.glyphs
+                        .iter()
+                        .fold((min[0], max[0]), |(left, right), glyph| {
+                            (
+                                left.min(glyph.x - overhang),
+                                right.max(glyph.x + glyph.w + overhang),
+                            )
+                        });
+                (
+                    [left, min[1].min(run.line_top)],
+                    [right, max[1].max(run.line_top + run.line_height)],
+                )
+            },
+        );
+
+        let bounds = area.bounds;
+        let glyphs = [
+            (area.left + min[0] * area.scale).max(bounds.left as f32),
+            (area.top + min[1] * area.scale).max(bounds.top as f32),
+            (area.left + max[0] * area.scale).min(bounds.right as f32),
+            (area.top + max[1] * area.scale).min(bounds.bottom as f32),
+        ];
+        if glyphs[0] >= glyphs[2] || glyphs[1] >= glyphs[3] {
+            return None;
+        }
+
+        // Offscreen glyphs aren't in the intermediate, so neither are their
+        // effects
+        let reach = area.effects.reach() * area.scale;
+        let left = (glyphs[0] - reach).max(bounds.left as f32).max(0.);
+        let top = (glyphs[1] - reach).max(bounds.top as f32).max(0.);
+        let right = (glyphs[2] + reach)
+            .min(bounds.right as f32)
+            .min(width as f32);
+        let bottom = (glyphs[3] + reach)
+            .min(bounds.bottom as f32)
+            .min(height as f32);
+        if left >= right || top >= bottom {
+            return None;
+        }
+
+        let effects = area.effects;
+        let shadow = effects.shadow.map_or([0.; 4], |shadow| {
+            [
+                shadow.offset[0] * area.scale,
+                shadow.offset[1] * area.scale,
+                shadow.blur * 0.5 * area.scale,
+                0.,
+            ]
+        });
+
+        let text = area.clip != ClipId::default();
+
+        Some(EffectsInstance {
+            rect: [left, top, right - left, bottom - top],
+            // Just behind the text, unless this draws the text too
+            depth: match text {
+                true => area.depth,
+                false => area.depth + BEHIND_TEXT_DEPTH,
+            },
+            shadow,
+            shadow_color: effects.shadow.map_or([0.; 4], |shadow| color(shadow.color)),
+            outline_color: effects
+                .outline
+                .map_or([0.; 4], |outline| color(outline.color)),
+            glow_color: effects.glow.map_or([0.; 4], |glow| color(glow.color)),
+            widths: [
+                effects
+                    .outline
+                    .map_or(0., |outline| outline.width * area.scale),
+                effects
+                    .glow
+                    .map_or(0., |glow| glow.radius * 0.5 * area.scale),
+            ],
+            glyphs,
+            clip: area.clip,
+            text: text as u32,
+            region: [left.floor(), top.floor(), right.ceil(), bottom.ceil()],
+            scratch: [0; 2],
+        })
+    }
+
+    fn create_intermediate(
+        &self,
+        device: &wgpu::Device,
+        [width, height]: [u32; 2],
+        layers: u32,
+    ) -> Intermediate {
+        let texture = |label, format, height| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        };
+
+        let text_view = texture("text_effects_intermediate_texture", self.format, height)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let horizontal_view = texture(
+            "text_effects_horizontal_texture",
+            HORIZONTAL_FORMAT,
+            height * layers,
+        )
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let horizontal_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.horizontal_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&text_view),
+            }],
+            label: Some("text_effects_horizontal_bind_group"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&text_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&horizontal_view),
+                },
+            ],
+            label: Some("text_effects_intermediate_bind_group"),
+        });
+
+        Intermediate {
+            text_view,
+            horizontal_view,
+            layers,
+            horizontal_bind_group,
+            bind_group,
+        }
+    }
+
+    /// Draws the text with effects into the intermediate texture, and runs
+    /// the horizontal pass of the effects on it.
+    pub fn render_intermediate(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        atlas: &glyphon::TextAtlas,
+        viewport: &glyphon::Viewport,
+    ) -> anyhow::Result<()> {
+        let Some(intermediate) = self
+            .intermediate
+            .as_ref()
+            .filter(|_| !self.depths.is_empty())
+        else {
+            return Ok(());
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("text_effects_intermediate_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &intermediate.text_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+        self.text.render(atlas, viewport, &mut render_pass)?;
+        drop(render_pass);
+
+        let mut horizontal_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("text_effects_horizontal_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &intermediate.horizontal_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+
+        horizontal_pass.set_pipeline(&self.horizontal_pipeline);
+        horizontal_pass.set_bind_group(0, &intermediate.horizontal_bind_group, &[]);
+        horizontal_pass.set_bind_group(1, &self.params_bind_group, &[]);
+        horizontal_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        horizontal_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        horizontal_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        horizontal_pass.draw_indexed(0..self.index_buffer.size(), 0, 0..self.depths.len() as u32);
+
+        Ok(())
+    }
+
+    /// Composites the effects of the areas with a depth in `depths` into
+    /// the pass the text is drawn in.
+    pub fn render_range(&self, render_pass: &mut wgpu::RenderPass, depths: RangeInclusive<f32>) {
+        let start = self.depths.partition_point(|depth| depth > depths.end());
+        let end = self.depths.partition_point(|depth| depth >= depths.start());
+
+        let Some(intermediate) = self.intermediate.as_ref().filter(|_| start < end) else {
+            return;
+        };
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &intermediate.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.params_bind_group, &[]);
+        render_pass.set_bind_group(2, self.clips.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.index_buffer.size(), 0, start as u32..end as u32);
+    }
+}
+
+/// Places the regions of `instances`, next to the depth and index of their
+/// areas, next to each other in rows of up to `max_layers` layers of `size`
+/// stacked on top of each other. Returns the layers used and how many
+/// instances fit.
+fn pack(
+    instances: &mut [(f32, usize, EffectsInstance)],
+    [width, height]: [u32; 2],
+    max_layers: u32,
+) -> (u32, usize) {
+    let (mut layer, mut x, mut y, mut row) = (0, 0, 0, 0);
+
+    for (index, (_, _, instance)) in instances.iter_mut().enumerate() {
+        let region = instance.region;
+        let (w, h) = (
+            (region[2] - region[0]) as u32,
+            (region[3] - region[1]) as u32,
+        );
+
+        if x + w > width {
+            (x, y, row) = (0, y + row, 0);
+        }
+        if y + h > height {
+            (layer, x, y, row) = (layer + 1, 0, 0, 0);
+        }
+        if layer >= max_layers {
+            return (max_layers, index);
+        }
+
+        instance.scratch = [x, layer * height + y];
+        x += w;
+        row = row.max(h);
+    }
+
+    (layer + 1, instances.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(width: f32, height: f32) -> (f32, usize, EffectsInstance) {
+        let instance = EffectsInstance {
+            rect: [0.; 4],
+            depth: 0.,
+            shadow: [0.; 4],
+            shadow_color: [0.; 4],
+            outline_color: [0.; 4],
+            glow_color: [0.; 4],
+            widths: [0.; 2],
+            glyphs: [0.; 4],
+            clip: ClipId::default(),
+            text: 0,
+            region: [10., 10., 10. + width, 10. + height],
+            scratch: [0; 2],
+        };
+
+        (0., 0, instance)
+    }
+
+    #[test]
+    fn packs_regions_apart() {
+        let mut instances = [
+            instance(60., 20.),
+            instance(30., 10.),
+            instance(20., 30.),
+            instance(100., 40.),
+            instance(100., 50.),
+        ];
+
+        assert_eq!(pack(&mut instances, [100, 60], 3), (3, 5));
+        assert_eq!(
+            instances.map(|(_, _, instance)| instance.scratch),
+            [[0, 0], [60, 0], [0, 20], [0, 60], [0, 120]]
+        );
+
+        // Regions past the last layer don't fit
+        assert_eq!(pack(&mut instances, [100, 60], 2), (2, 4));
+    }
+}
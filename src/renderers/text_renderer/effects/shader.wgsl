@@ -0,0 +1,301 @@
+struct Params {
+    screen_resolution: vec2<u32>,
+    layers: u32,  // Times the target's height of the horizontal texture
+    _pad: u32,
+};
+@group(1) @binding(0)
+var<uniform> params: Params;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(1) rect: vec4<f32>,  // [left, top, width, height] the effects cover
+    @location(2) depth: f32,
+    @location(3) shadow: vec4<f32>,  // [offset_x, offset_y, sigma, -]
+    @location(4) shadow_color: vec4<f32>,
+    @location(5) outline_color: vec4<f32>,
+    @location(6) glow_color: vec4<f32>,
+    @location(7) widths: vec2<f32>,  // [outline width, glow sigma]
+    @location(8) glyphs: vec4<f32>,  // [left, top, right, bottom] of the area's glyphs
+    @location(9) clip: vec2<u32>,
+    @location(10) text: u32,  // Whether the glyphs are drawn from here too
+    @location(11) region: vec4<f32>,  // [left, top, right, bottom] pixels of the horizontal pass
+    @location(12) scratch: vec2<u32>,  // Top left of the region in the horizontal texture
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) shadow: vec4<f32>,
+    @location(1) shadow_color: vec4<f32>,
+    @location(2) outline_color: vec4<f32>,
+    @location(3) glow_color: vec4<f32>,
+    @location(4) widths: vec2<f32>,
+    @location(5) @interpolate(flat) glyphs: vec4<f32>,
+    @location(6) @interpolate(flat) clip: vec2<u32>,
+    @location(7) @interpolate(flat) text: u32,
+    @location(8) @interpolate(flat) region: vec4<f32>,
+    @location(9) @interpolate(flat) scratch: vec2<u32>,
+};
+
+// Text with effects, drawn at the same pixels as in the target
+@group(0) @binding(0)
+var t_text: texture_2d<f32>;
+
+// Output of the horizontal pass: the shadow and glow blurred horizontally,
+// and the horizontal distance to the glyphs for the outline
+@group(0) @binding(1)
+var t_horizontal: texture_2d<f32>;
+
+fn output(instance: InstanceInput, position: vec2<f32>, size: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+
+    let ndc = (position / size) * 2.0 - vec2<f32>(1.0, 1.0);
+
+    out.clip_position = vec4<f32>(ndc.x, -ndc.y, instance.depth, 1.0);
+    out.shadow = instance.shadow;
+    out.shadow_color = instance.shadow_color;
+    out.outline_color = instance.outline_color;
+    out.glow_color = instance.glow_color;
+    out.widths = instance.widths;
+    out.glyphs = instance.glyphs;
+    out.clip = instance.clip;
+    out.text = instance.text;
+    out.region = instance.region;
+    out.scratch = instance.scratch;
+
+    return out;
+}
+
+@vertex
+fn vs_horizontal(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let region = instance.region.zw - instance.region.xy;
+    let position = vec2<f32>(instance.scratch) + model.position * region;
+    let size = vec2<f32>(params.screen_resolution * vec2<u32>(1u, params.layers));
+    return output(instance, position, size);
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let position = instance.rect.xy + model.position * instance.rect.zw;
+    return output(instance, position, vec2<f32>(params.screen_resolution));
+}
+
+// Premultiplied glyphs of one area, so that effects don't pick up the text
+// of areas next to it
+fn glyph(position: vec2<f32>, glyphs: vec4<f32>) -> vec4<f32> {
+    if any(position < glyphs.xy) || any(position >= glyphs.zw) {
+        return vec4<f32>(0.0);
+    }
+
+    let max_texel = vec2<i32>(textureDimensions(t_text)) - vec2<i32>(1);
+    let texel = vec2<i32>(floor(position));
+    if any(texel < vec2<i32>(0)) || any(texel > max_texel) {
+        return vec4<f32>(0.0);
+    }
+
+    return textureLoad(t_text, texel, 0);
+}
+
+fn coverage(position: vec2<f32>, glyphs: vec4<f32>) -> f32 {
+    return glyph(position, glyphs).a;
+}
+
+// Taps to each side of a gaussian, and the pixels between them. Large
+// sigmas are sampled sparser, so that every pass reads at most 19 texels.
+fn gaussian_taps(sigma: f32) -> vec2<f32> {
+    if sigma <= 0.0 {
+        return vec2<f32>(0.0, 1.0);
+    }
+
+    let taps = min(ceil(sigma * 3.0), 9.0);
+    return vec2<f32>(taps, sigma * 3.0 / taps);
+}
+
+fn gaussian(offset: f32, sigma: f32) -> f32 {
+    if sigma <= 0.0 {
+        return 1.0;
+    }
+
+    return exp(-offset * offset / (2.0 * sigma * sigma));
+}
+
+// Taps to each side of the dilation of the outline, of which up to 8 are
+// exact
+fn dilation_taps(width: f32) -> vec2<f32> {
+    let taps = min(ceil(width), 8.0);
+    return vec2<f32>(taps, width / max(taps, 1.0));
+}
+
+// Stands for no glyphs within reach of the outline
+const FAR: f32 = 10000.0;
+
+fn horizontal_blur(position: vec2<f32>, sigma: f32, glyphs: vec4<f32>) -> f32 {
+    let taps = gaussian_taps(sigma);
+
+    var sum = 0.0;
+    var total = 0.0;
+    for (var x = -taps.x; x <= taps.x; x += 1.0) {
+        let offset = x * taps.y;
+        let weight = gaussian(offset, sigma);
+        sum += coverage(position + vec2<f32>(offset, 0.0), glyphs) * weight;
+        total += weight;
+    }
+
+    return sum / total;
+}
+
+// Horizontal distance to the closest glyph, where partly covered texels
+// are further away
+fn horizontal_distance(position: vec2<f32>, width: f32, glyphs: vec4<f32>) -> f32 {
+    let taps = dilation_taps(width);
+
+    var result = FAR;
+    for (var x = -taps.x; x <= taps.x; x += 1.0) {
+        let offset = x * taps.y;
+        let alpha = coverage(position + vec2<f32>(offset, 0.0), glyphs);
+        if alpha > 0.0 {
+            result = min(result, abs(offset) + 1.0 - alpha);
+        }
+    }
+
+    return result;
+}
+
+@fragment
+fn fs_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Same pixel as in the target
+    let position = in.clip_position.xy - vec2<f32>(in.scratch.xy) + in.region.xy;
+
+    var result = vec4<f32>(0.0, 0.0, FAR, 0.0);
+    if in.shadow_color.a > 0.0 {
+        // The vertical pass offsets the shadow vertically
+        let shadow = position - vec2<f32>(in.shadow.x, 0.0);
+        result.r = horizontal_blur(shadow, in.shadow.z, in.glyphs);
+    }
+    if in.glow_color.a > 0.0 {
+        result.g = horizontal_blur(position, in.widths.y, in.glyphs);
+    }
+    if in.outline_color.a > 0.0 && in.widths.x > 0.0 {
+        result.b = horizontal_distance(position, in.widths.x, in.glyphs);
+    }
+
+    return result;
+}
+
+// Output of the horizontal pass at `position` of the target, or nothing
+// outside of the instance's region
+fn horizontal(position: vec2<f32>, region: vec4<f32>, scratch: vec2<u32>) -> vec4<f32> {
+    if any(position < region.xy) || any(position >= region.zw) {
+        return vec4<f32>(0.0, 0.0, FAR, 0.0);
+    }
+
+    let texel = vec2<i32>(floor(position - region.xy)) + vec2<i32>(scratch.xy);
+    return textureLoad(t_horizontal, texel, 0);
+}
+
+fn vertical_blur(
+    position: vec2<f32>,
+    sigma: f32,
+    channel: u32,
+    region: vec4<f32>,
+    scratch: vec2<u32>,
+) -> f32 {
+    let taps = gaussian_taps(sigma);
+
+    var sum = 0.0;
+    var total = 0.0;
+    for (var y = -taps.x; y <= taps.x; y += 1.0) {
+        let offset = y * taps.y;
+        let weight = gaussian(offset, sigma);
+        sum += horizontal(position + vec2<f32>(0.0, offset), region, scratch)[channel] * weight;
+        total += weight;
+    }
+
+    return sum / total;
+}
+
+// Coverage of the glyphs grown by `width`
+fn dilated(position: vec2<f32>, width: f32, region: vec4<f32>, scratch: vec2<u32>) -> f32 {
+    if width <= 0.0 {
+        return 0.0;
+    }
+
+    let taps = dilation_taps(width);
+
+    var distance = FAR;
+    for (var y = -taps.x; y <= taps.x; y += 1.0) {
+        let offset = y * taps.y;
+        let x = horizontal(position + vec2<f32>(0.0, offset), region, scratch).b;
+        distance = min(distance, length(vec2<f32>(x, offset)));
+    }
+
+    return clamp(width + 0.5 - distance, 0.0, 1.0);
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    var result = vec3<f32>(0.0);
+    for (var i = 0; i < 3; i = i + 1) {
+        if c[i] <= 0.04045 {
+            result[i] = c[i] / 12.92;
+        } else {
+            result[i] = pow((c[i] + 0.055) / 1.055, 2.4);
+        }
+    }
+    return result;
+}
+
+fn premultiplied(color: vec4<f32>, alpha: f32) -> vec4<f32> {
+    let a = color.a * alpha;
+    return vec4<f32>(srgb_to_linear(color.rgb) * a, a);
+}
+
+fn over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    return top + bottom * (1.0 - top.a);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let position = in.clip_position.xy;
+    var color = vec4<f32>(0.0);
+
+    // Back to front: shadow, glow, outline. The text itself is drawn on top
+    // by glyphon afterwards, unless it's clipped.
+    if in.shadow_color.a > 0.0 {
+        let shadow = position - vec2<f32>(0.0, in.shadow.y);
+        let alpha = vertical_blur(shadow, in.shadow.z, 0u, in.region, in.scratch);
+        color = premultiplied(in.shadow_color, alpha);
+    }
+
+    if in.glow_color.a > 0.0 {
+        // Twice as strong as a shadow, which would barely show around thin
+        // glyphs
+        let blurred = vertical_blur(position, in.widths.y, 1u, in.region, in.scratch);
+        let alpha = min(blurred * 2.0, 1.0);
+        color = over(premultiplied(in.glow_color, alpha), color);
+    }
+
+    if in.outline_color.a > 0.0 {
+        let alpha = dilated(position, in.widths.x, in.region, in.scratch);
+        color = over(premultiplied(in.outline_color, alpha), color);
+    }
+
+    if in.text != 0u {
+        color = over(glyph(position, in.glyphs), color);
+    }
+    color *= clip_coverage(position, in.clip);
+
+    // Keep empty pixels out of the depth buffer
+    if color.a < 0.001 {
+        discard;
+    }
+
+    return color;
+}
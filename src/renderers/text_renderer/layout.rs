@@ -0,0 +1,223 @@
+use super::{RichText, TextArea};
+
+/// Appended to text that [`TextLayout`] cuts off.
+pub const ELLIPSIS: &str = "…";
+
+/// Where text is placed vertically inside the box of a [`TextLayout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Shapes text into a box, cutting it off with an [`ELLIPSIS`] when it
+/// doesn't fit, and positions it inside that box.
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::text_renderer::TextLayout;
+///
+/// // Two lines, then "…"
+/// let layout = TextLayout {
+///     width: Some(280.),
+///     height: Some(48.),
+///     max_lines: Some(2),
+///     ..Default::default()
+/// };
+///
+/// layout.set_text(&mut font_system, &mut buffer, body, &attrs);
+/// let area = layout.area(&buffer, 16., 40.);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextLayout {
+    /// Width of the box, or `None` for text as wide as its longest line.
+    pub width: Option<f32>,
+    /// Height of the box, or `None` for text as high as its lines. Only
+    /// used for the vertical alignment, lines are limited by `max_lines`.
+    pub height: Option<f32>,
+    /// Lines after which the text is cut off.
+    pub max_lines: Option<usize>,
+    /// How lines wider than `width` are wrapped. Without wrapping, the text
+    /// is cut off at the first line that is too wide.
+    pub wrap: glyphon::Wrap,
+    /// Horizontal alignment of the lines, `None` aligning them to the start
+    /// of their direction.
+    pub align: Option<glyphon::cosmic_text::Align>,
+    pub vertical_align: VerticalAlign,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            max_lines: None,
+            wrap: glyphon::Wrap::WordOrGlyph,
+            align: None,
+            vertical_align: VerticalAlign::Top,
+        }
+    }
+}
+
+impl TextLayout {
+    /// Shapes `text` into `buffer`, cut off if it doesn't fit the box.
+    pub fn set_text(
+        &self,
+        font_system: &mut glyphon::FontSystem,
+        buffer: &mut glyphon::Buffer,
+        text: &str,
+        attrs: &glyphon::Attrs,
+    ) {
+        self.shape(
+            font_system,
+            buffer,
+            text,
+            |font_system, buffer, end, ellipsis| {
+                let text = match ellipsis {
+                    true => format!("{}{ELLIPSIS}", &text[..end]),
+                    false => text.to_owned(),
+                };
+                buffer.set_text(
+                    font_system,
+                    &text,
+                    attrs,
+                    glyphon::Shaping::Advanced,
+                    self.align,
+                );
+            },
+        );
+    }
+
+    /// Shapes the spans of `text` into `buffer` on top of `attrs`, cut off
+    /// if they don't fit the box.
+    pub fn set_rich_text(
+        &self,
+        font_system: &mut glyphon::FontSystem,
+        buffer: &mut glyphon::Buffer,
+        text: &RichText,
+        attrs: &glyphon::Attrs,
+    ) {
+        // Metadata tells glyphs of spans apart from the rest
+        let attrs = attrs.clone().metadata(0);
+
+        self.shape(
+            font_system,
+            buffer,
+            &text.text(),
+            |font_system, buffer, end, ellipsis| {
+                let mut start = 0;
+                let spans = text
+                    .spans
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, span)| {
+                        let len = span.text.len().min(end.saturating_sub(start));
+                        start += span.text.len();
+                        (len > 0).then(|| (&span.text[..len], span.attrs(&attrs, i)))
+                    })
+                    .collect::<Vec<_>>();
+
+                // Styled like the text it cuts off, but not part of a span
+                let ellipsis = ellipsis.then(|| {
+                    let attrs = spans
+                        .last()
+                        .map_or(attrs.clone(), |(_, attrs)| attrs.clone().metadata(0));
+                    (ELLIPSIS, attrs)
+                });
+
+                buffer.set_rich_text(
+                    font_system,
+                    spans.into_iter().chain(ellipsis),
+                    &attrs,
+                    glyphon::Shaping::Advanced,
+                    self.align,
+                );
+            },
+        );
+    }
+
+    /// Shapes `text` with `set_text`, which sets the first `end` bytes of
+    /// `text` and an ellipsis if asked to. Finds the longest text that fits
+    /// with a binary search over its characters.
+    fn shape(
+        &self,
+        font_system: &mut glyphon::FontSystem,
+        buffer: &mut glyphon::Buffer,
+        text: &str,
+        mut set_text: impl FnMut(&mut glyphon::FontSystem, &mut glyphon::Buffer, usize, bool),
+    ) {
+        // Without a height, the buffer shapes and lays out all lines
+        buffer.set_wrap(font_system, self.wrap);
+        buffer.set_size(font_system, self.width, None);
+
+        set_text(font_system, buffer, text.len(), false);
+        if self.fits(buffer) {
+            return;
+        }
+
+        let ends = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+        let end = |i: usize| text[..ends[i]].trim_end().len();
+
+        // The ellipsis on its own always "fits", nothing can be shown then
+        let (mut fitting, mut overflowing) = (0, ends.len());
+        while overflowing - fitting > 1 {
+            let mid = (fitting + overflowing) / 2;
+            set_text(font_system, buffer, end(mid), true);
+            match self.fits(buffer) {
+                true => fitting = mid,
+                false => overflowing = mid,
+            }
+        }
+
+        set_text(font_system, buffer, end(fitting), true);
+    }
+
+    fn fits(&self, buffer: &glyphon::Buffer) -> bool {
+        let width = self.width.unwrap_or(f32::INFINITY);
+
+        buffer
+            .layout_runs()
+            .enumerate()
+            .all(|(i, run)| self.max_lines.is_none_or(|max| i < max) && run.line_w <= width)
+    }
+
+    /// An area drawing `buffer` aligned inside the box with its top left
+    /// corner at `left` and `top`, and clipped to it.
+    pub fn area<'a>(&self, buffer: &'a glyphon::Buffer, left: f32, top: f32) -> TextArea<'a> {
+        let [_, text_height] = measure(buffer);
+        let free = self.height.map_or(0., |height| height - text_height);
+        let offset = match self.vertical_align {
+            VerticalAlign::Top => 0.,
+            VerticalAlign::Center => free / 2.,
+            VerticalAlign::Bottom => free,
+        };
+
+        TextArea {
+            bounds: glyphon::TextBounds {
+                left: left.floor() as i32,
+                top: top.floor() as i32,
+                right: self
+                    .width
+                    .map_or(i32::MAX, |width| (left + width).ceil() as i32),
+                bottom: self
+                    .height
+                    .map_or(i32::MAX, |height| (top + height).ceil() as i32),
+            },
+            ..TextArea::new(buffer, left, top + offset)
+        }
+    }
+}
+
+/// `[width, height]` of the text shaped into `buffer`, e.g. to size the box
+/// around it before laying it out.
+pub fn measure(buffer: &glyphon::Buffer) -> [f32; 2] {
+    buffer.layout_runs().fold([0., 0.], |[width, height], run| {
+        [
+            width.max(run.line_w),
+            height.max(run.line_top + run.line_height),
+        ]
+    })
+}
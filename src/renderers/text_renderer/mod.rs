@@ -1,10 +1,118 @@
+mod cursor;
+mod effects;
+mod layout;
+mod rich;
+
+use crate::clip::{ClipId, ClipStack};
+use crate::viewport::Viewport;
+use effects::EffectsRenderer;
+pub use effects::{TextEffects, TextGlow, TextOutline, TextShadow};
+pub use layout::{ELLIPSIS, TextLayout, VerticalAlign, measure};
+pub use rich::{RichText, Span, SpanRegion};
+use std::ops::RangeInclusive;
 use wgpu::{MultisampleState, TextureFormat};
 
+/// How far behind its glyphs the shapes drawn for a [`TextArea`] are, like
+/// its effects and selections. The depth buffer holds depths close to 1 in
+/// steps of 2^-24, so this stays 16 steps apart at any depth.
+pub const BEHIND_TEXT_DEPTH: f32 = 1. / (1 << 20) as f32;
+
+/// Text drawn by the [`TextRenderer`], positioned in pixels of the target
+/// like shapes and textures.
+#[derive(Clone)]
+pub struct TextArea<'a> {
+    pub buffer: &'a glyphon::Buffer,
+    pub left: f32,
+    pub top: f32,
+    /// Scale of the text, e.g. the scale factor of the window.
+    pub scale: f32,
+    /// Pixels of the target the text is clipped to, e.g. from
+    /// [`ClipStack::text_bounds`].
+    pub bounds: glyphon::TextBounds,
+    /// Clips the text is confined to on top of its bounds, including their
+    /// rounded corners, once uploaded with
+    /// [`TextRenderer::prepare_clips`].
+    pub clip: ClipId,
+    /// Color of all glyphs without a color of their own.
+    pub color: glyphon::Color,
+    /// Depth between 0 and 1, where smaller depths are drawn on top.
+    pub depth: f32,
+    /// Shadow, outline and glow drawn behind the glyphs.
+    pub effects: TextEffects,
+}
+
+impl<'a> TextArea<'a> {
+    /// White, unclipped and unscaled text at depth 0.
+    pub fn new(buffer: &'a glyphon::Buffer, left: f32, top: f32) -> Self {
+        Self {
+            buffer,
+            left,
+            top,
+            scale: 1.,
+            bounds: glyphon::TextBounds::default(),
+            color: glyphon::Color::rgb(255, 255, 255),
+            depth: 0.,
+            effects: TextEffects::default(),
+            clip: ClipId::default(),
+        }
+    }
+
+    /// Whether the text is drawn through the intermediate of the effects
+    /// renderer instead of straight into the target.
+    fn is_composited(&self) -> bool {
+        !self.effects.is_empty() || self.clip != ClipId::default()
+    }
+
+    fn to_glyphon(&self) -> glyphon::TextArea<'a> {
+        glyphon::TextArea {
+            buffer: self.buffer,
+            left: self.left,
+            top: self.top,
+            scale: self.scale,
+            bounds: self.bounds,
+            default_color: self.color,
+            custom_glyphs: &[],
+        }
+    }
+}
+
+/// Draws [`TextArea`]s into a render pass with a depth buffer, sharing the
+/// coordinate system of the [`Viewport`] given to
+/// [`prepare`](Self::prepare).
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::text_renderer::{TextArea, TextRenderer, TextShadow};
+///
+/// let mut text_renderer = TextRenderer::new(&device, &queue, format);
+///
+/// let mut label = TextArea::new(&buffer, 8., 8.);
+/// label.depth = 0.1;
+///
+/// label.effects.shadow = Some(TextShadow {
+///     offset: [0., 1.],
+///     blur: 4.,
+///     color: glyphon::Color::rgba(0, 0, 0, 160),
+/// });
+///
+/// text_renderer.prepare(&device, &queue, &viewport, &[label], &mut font_system)?;
+///
+/// // Effects are drawn from the text rendered on its own first
+/// text_renderer.render_effects(&mut encoder)?;
+/// let mut render_pass = encoder.begin_render_pass(&descriptor);
+/// text_renderer.render(&mut render_pass)?;
+/// ```
 pub struct TextRenderer {
-    pub swash_cache: glyphon::SwashCache,
-    pub viewport: glyphon::Viewport,
-    pub atlas: glyphon::TextAtlas,
-    pub renderer: glyphon::TextRenderer,
+    swash_cache: glyphon::SwashCache,
+    viewport: glyphon::Viewport,
+    atlas: glyphon::TextAtlas,
+    // One glyphon renderer per depth, since glyphon only derives depth from
+    // the metadata of each glyph. All of them share the atlas.
+    renderers: Vec<glyphon::TextRenderer>,
+    // Depths of the renderers holding text of the last prepare, back to front
+    depths: Vec<f32>,
+    effects: EffectsRenderer,
 }
 
 impl TextRenderer {
@@ -12,55 +120,134 @@ impl TextRenderer {
         let swash_cache = glyphon::SwashCache::new();
         let cache = glyphon::Cache::new(device);
         let mut atlas = glyphon::TextAtlas::new(device, queue, &cache, texture_format);
-        let renderer = glyphon::TextRenderer::new(
-            &mut atlas,
-            device,
-            MultisampleState::default(),
-            Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-        );
+        let effects = EffectsRenderer::new(device, &mut atlas, texture_format);
 
         Self {
             swash_cache,
             viewport: glyphon::Viewport::new(device, &cache),
             atlas,
-            renderer,
+            renderers: Vec::new(),
+            depths: Vec::new(),
+            effects,
         }
     }
 
+    /// Uploads the clips that [`TextArea::clip`] refers to.
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.effects.prepare_clips(device, queue, clips);
+    }
+
+    /// Prepares `text` for [`render`](Self::render). The effects and clips
+    /// of each area take up a part of a texture of at most the device's
+    /// largest size, and areas past that are drawn without them.
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        text: Vec<glyphon::TextArea>,
+        viewport: &Viewport,
+        text: &[TextArea],
         font_system: &mut glyphon::FontSystem,
     ) -> anyhow::Result<()> {
-        if text.is_empty() {
-            return Ok(());
-        }
+        let resolution = viewport.resolution();
+        self.viewport.update(
+            queue,
+            glyphon::Resolution {
+                width: resolution.width,
+                height: resolution.height,
+            },
+        );
 
-        self.renderer.prepare_with_depth(
+        // Composited areas the effects renderer has no room for are drawn
+        // plainly instead of not at all
+        let plain = self.effects.prepare(
             device,
             queue,
-            font_system,
+            resolution,
+            text,
             &mut self.atlas,
             &self.viewport,
-            text,
             &mut self.swash_cache,
-            |metadata| f32::from_bits(metadata as u32),
+            font_system,
         )?;
 
+        self.depths.clear();
+        self.depths.extend(text.iter().map(|area| area.depth));
+        self.depths.sort_by(|a, b| b.total_cmp(a));
+        self.depths.dedup();
+
+        // Renderers of depths that are gone would keep their buffers for
+        // good, and glyphon caches the pipelines of new ones
+        self.renderers.truncate(self.depths.len());
+        while self.renderers.len() < self.depths.len() {
+            self.renderers.push(glyphon::TextRenderer::new(
+                &mut self.atlas,
+                device,
+                MultisampleState::default(),
+                Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+            ));
+        }
+
+        self.depths
+            .iter()
+            .zip(&mut self.renderers)
+            .try_for_each(|(&depth, renderer)| {
+                // Clipped text is drawn from the intermediate instead
+                let areas = text
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, area)| {
+                        area.depth == depth && (area.clip == ClipId::default() || plain.contains(i))
+                    })
+                    .map(|(_, area)| area.to_glyphon());
+
+                renderer.prepare_with_depth(
+                    device,
+                    queue,
+                    font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    areas,
+                    &mut self.swash_cache,
+                    |_| depth,
+                )
+            })?;
+
         Ok(())
     }
 
-    pub fn render(&mut self, render_pass: &mut wgpu::RenderPass) -> anyhow::Result<()> {
-        self.renderer
-            .render(&self.atlas, &self.viewport, render_pass)?;
+    /// Draws the text with effects on its own, which they are composited
+    /// from. Has to be called before the render pass of
+    /// [`render`](Self::render) begins.
+    pub fn render_effects(&self, encoder: &mut wgpu::CommandEncoder) -> anyhow::Result<()> {
+        self.effects
+            .render_intermediate(encoder, &self.atlas, &self.viewport)
+    }
+
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass) -> anyhow::Result<()> {
+        self.render_range(render_pass, f32::NEG_INFINITY..=f32::INFINITY)
+    }
+
+    /// Draws only the prepared areas with a depth in `depths`, e.g. to
+    /// interleave text with primitives drawn in other passes.
+    pub fn render_range(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        depths: RangeInclusive<f32>,
+    ) -> anyhow::Result<()> {
+        self.effects.render_range(render_pass, depths.clone());
+        self.depths
+            .iter()
+            .zip(&self.renderers)
+            .filter(|(depth, _)| depths.contains(depth))
+            .try_for_each(|(_, renderer)| {
+                renderer.render(&self.atlas, &self.viewport, render_pass)
+            })?;
 
         Ok(())
     }
@@ -0,0 +1,526 @@
+use super::TextArea;
+
+/// Part of a [`RichText`] drawn with one style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub weight: glyphon::Weight,
+    pub style: glyphon::Style,
+    /// Color of the span, or `None` for the color of its [`TextArea`].
+    pub color: Option<glyphon::Color>,
+    pub underline: bool,
+    /// Target of the link the span is part of.
+    pub link: Option<String>,
+    /// Source of the image the span stands in for, whose alternative text
+    /// is the span's text.
+    pub image: Option<String>,
+}
+
+impl Span {
+    /// Unstyled `text`.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            weight: glyphon::Weight::NORMAL,
+            style: glyphon::Style::Normal,
+            color: None,
+            underline: false,
+            link: None,
+            image: None,
+        }
+    }
+
+    /// Attributes of the span on top of `attrs`.
+    pub(crate) fn attrs<'a>(&self, attrs: &glyphon::Attrs<'a>, index: usize) -> glyphon::Attrs<'a> {
+        let attrs = attrs
+            .clone()
+            .weight(self.weight)
+            .style(self.style)
+            .metadata(index + 1);
+
+        match self.color {
+            Some(color) => attrs.color(color),
+            None => attrs,
+        }
+    }
+}
+
+/// A rectangle covered by a span, in pixels of the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpanRegion {
+    /// Index of the span in [`RichText::spans`].
+    pub span: usize,
+    /// `[left, top, width, height]`
+    pub rect: [f32; 4],
+}
+
+/// Text made of differently styled [`Span`]s, shaped with
+/// [`TextLayout::set_rich_text`](super::TextLayout::set_rich_text).
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::text_renderer::{RichText, TextLayout};
+///
+/// let body = RichText::from_markup("<b>Build failed</b>, see <a href=\"https://ci\">the log</a>");
+/// layout.set_rich_text(&mut font_system, &mut buffer, &body, &attrs);
+///
+/// let area = layout.area(&buffer, 16., 40.);
+/// if let Some(href) = body.link_at(&area, cursor_x, cursor_y) {
+///     open(href);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RichText {
+    pub spans: Vec<Span>,
+}
+
+impl RichText {
+    pub fn new(spans: Vec<Span>) -> Self {
+        Self { spans }
+    }
+
+    /// Parses the markup of freedesktop notification bodies: `<b>`, `<i>`,
+    /// `<u>`, `<a href="…">` and `<img src="…" alt="…"/>`, with XML
+    /// entities.
+    ///
+    /// Links are underlined and images replaced by their alternative text.
+    /// Other tags are dropped while keeping their content, and text that
+    /// isn't valid markup is kept as is: a `<` only starts a tag when a name
+    /// or `/` follows it, so `1 < 2` or `<3` stay literal.
+    pub fn from_markup(markup: &str) -> Self {
+        let mut spans: Vec<Span> = Vec::new();
+        let (mut bold, mut italic, mut underline) = (0u32, 0u32, 0u32);
+        let mut links: Vec<String> = Vec::new();
+
+        let mut push = |text: String, image: Option<String>, bold, italic, underline, link| {
+            if text.is_empty() {
+                return;
+            }
+
+            let span = Span {
+                text,
+                weight: match bold {
+                    0 => glyphon::Weight::NORMAL,
+                    _ => glyphon::Weight::BOLD,
+                },
+                style: match italic {
+                    0 => glyphon::Style::Normal,
+                    _ => glyphon::Style::Italic,
+                },
+                color: None,
+                underline,
+                link,
+                image,
+            };
+
+            // Text between tags that don't change the style, e.g. unknown ones
+            match spans.last_mut() {
+                Some(last)
+                    if last.image.is_none()
+                        && span.image.is_none()
+                        && last.weight == span.weight
+                        && last.style == span.style
+                        && last.underline == span.underline
+                        && last.link == span.link =>
+                {
+                    last.text.push_str(&span.text)
+                }
+                _ => spans.push(span),
+            }
+        };
+
+        let mut rest = markup;
+        while !rest.is_empty() {
+            let (text, tag) = match find_tag(rest) {
+                Some((start, end)) => (&rest[..start], Some(&rest[start + 1..end])),
+                None => (rest, None),
+            };
+
+            let link = links.last().cloned();
+            push(
+                decode_entities(text),
+                None,
+                bold,
+                italic,
+                underline > 0 || link.is_some(),
+                link,
+            );
+
+            let Some(tag) = tag else {
+                break;
+            };
+            rest = &rest[text.len() + tag.len() + 2..];
+
+            let (closing, tag) = match tag.strip_prefix('/') {
+                Some(tag) => (true, tag),
+                None => (false, tag.trim_end_matches('/')),
+            };
+            let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+
+            match (name.to_ascii_lowercase().as_str(), closing) {
+                ("b", false) => bold += 1,
+                ("b", true) => bold = bold.saturating_sub(1),
+                ("i", false) => italic += 1,
+                ("i", true) => italic = italic.saturating_sub(1),
+                ("u", false) => underline += 1,
+                ("u", true) => underline = underline.saturating_sub(1),
+                ("a", false) => links.push(attribute(attributes, "href").unwrap_or_default()),
+                ("a", true) => drop(links.pop()),
+                ("img", false) => {
+                    let link = links.last().cloned();
+                    push(
+                        attribute(attributes, "alt").unwrap_or_default(),
+                        attribute(attributes, "src"),
+                        bold,
+                        italic,
+                        underline > 0 || link.is_some(),
+                        link,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Self { spans }
+    }
+
+    /// Text of all spans.
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    /// Rectangles covered by the spans of the text `area` draws, which has
+    /// to be shaped from this text. Spans broken across lines have a
+    /// rectangle per line.
+    pub fn regions(&self, area: &TextArea) -> Vec<SpanRegion> {
+        self.layout_regions(area)
+            .into_iter()
+            .map(|(region, _, _)| region)
+            .collect()
+    }
+
+    /// Like [`regions`](Self::regions), together with the baseline of the
+    /// line of each region and the font size of its first glyph.
+    fn layout_regions(&self, area: &TextArea) -> Vec<(SpanRegion, f32, f32)> {
+        let mut regions: Vec<(SpanRegion, f32, f32)> = Vec::new();
+
+        area.buffer.layout_runs().for_each(|run| {
+            let first = regions.len();
+            run.glyphs.iter().for_each(|glyph| {
+                // Metadata 0 is text without a span, e.g. the ellipsis
+                let Some(span) = glyph.metadata.checked_sub(1) else {
+                    return;
+                };
+
+                let left = area.left + glyph.x * area.scale;
+                let width = glyph.w * area.scale;
+                match regions[first..].last_mut() {
+                    Some((region, _, _)) if region.span == span => {
+                        let right = (left + width).max(region.rect[0] + region.rect[2]);
+                        region.rect[0] = region.rect[0].min(left);
+                        region.rect[2] = right - region.rect[0];
+                    }
+                    _ => regions.push((
+                        SpanRegion {
+                            span,
+                            rect: [
+                                left,
+                                area.top + run.line_top * area.scale,
+                                width,
+                                run.line_height * area.scale,
+                            ],
+                        },
+                        run.line_y,
+                        glyph.font_size,
+                    )),
+                }
+            });
+        });
+
+        regions
+    }
+
+    /// Regions of the links in the text `area` draws, to hit-test them
+    /// against the pointer.
+    pub fn links(&self, area: &TextArea) -> Vec<(&str, [f32; 4])> {
+        self.regions(area)
+            .into_iter()
+            .filter_map(|region| {
+                let link = self.spans.get(region.span)?.link.as_deref()?;
+                Some((link, region.rect))
+            })
+            .collect()
+    }
+
+    /// Target of the link at `x` and `y` in the text `area` draws.
+    pub fn link_at(&self, area: &TextArea, x: f32, y: f32) -> Option<&str> {
+        self.links(area)
+            .into_iter()
+            .find(|(_, [left, top, width, height])| {
+                (*left..left + width).contains(&x) && (*top..top + height).contains(&y)
+            })
+            .map(|(link, _)| link)
+    }
+
+    /// Lines under the underlined spans of the text `area` draws, which
+    /// glyphon can't draw itself.
+    #[cfg(feature = "shape_renderer")]
+    pub fn underlines(&self, area: &TextArea) -> Vec<crate::shape_renderer::ShapeInstance> {
+        self.layout_regions(area)
+            .into_iter()
+            .filter_map(|(region, line_y, font_size)| {
+                let span = self.spans.get(region.span)?;
+                if !span.underline {
+                    return None;
+                }
+
+                // Below the baseline of the line the region is on
+                let [left, _, width, _] = region.rect;
+                let thickness = (font_size / 14.).max(1.) * area.scale;
+                let y = area.top + (line_y + font_size * 0.1) * area.scale;
+
+                let color = span.color.unwrap_or(area.color);
+                Some(crate::shape_renderer::ShapeInstance {
+                    rect_pos: [left, y.round()],
+                    rect_size: [width, thickness],
+                    rect_color: [color.r(), color.g(), color.b(), color.a()]
+                        .map(|c| c as f32 / 255.),
+                    depth: area.depth,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Value of the attribute `name` in the attributes of a tag.
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    loop {
+        let (key, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, after) = value[1..].split_once(quote)?;
+
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(decode_entities(value));
+        }
+        rest = after;
+    }
+}
+
+/// Byte offsets of the `<` and `>` of the first tag in `text`. Any `<` not
+/// followed by a name or `/`, or without a `>` before the next `<`, is text.
+fn find_tag(text: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+
+    while let Some(start) = text[from..].find('<').map(|start| from + start) {
+        let tag = &text[start + 1..];
+        let named = tag.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+
+        match tag.find(['<', '>']) {
+            Some(end) if named && tag[end..].starts_with('>') => {
+                return Some((start, start + 1 + end));
+            }
+            _ => from = start + 1,
+        }
+    }
+
+    None
+}
+
+/// Replaces XML entities with the characters they stand for, keeping
+/// unknown ones as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let char = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        });
+
+        match (entity, char) {
+            (Some(entity), Some(char)) => {
+                decoded.push(char);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stray_angle_brackets_are_text() {
+        for text in ["1 < 2 and 3 > 2", "I <3 you > them", "a <", "<<b>bold</b>"] {
+            let expected = text.replace("<b>", "").replace("</b>", "");
+            assert_eq!(RichText::from_markup(text).text(), expected);
+        }
+
+        let spans = RichText::from_markup("x < <b>y</b>").spans;
+        assert_eq!(spans[0].text, "x < ");
+        assert_eq!(spans[1].text, "y");
+        assert_eq!(spans[1].weight, glyphon::Weight::BOLD);
+    }
+
+    /// Text, bold, italic and underline of every span.
+    fn styles(markup: &str) -> Vec<(String, bool, bool, bool)> {
+        RichText::from_markup(markup)
+            .spans
+            .into_iter()
+            .map(|span| {
+                (
+                    span.text,
+                    span.weight == glyphon::Weight::BOLD,
+                    span.style == glyphon::Style::Italic,
+                    span.underline,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_styles() {
+        let span = |text: &str, bold, italic, underline| (text.to_owned(), bold, italic, underline);
+
+        // Nesting a style it's already in keeps it until the outer tag closes
+        assert_eq!(
+            styles("<b>a<i>b<b>c</b>d</i>e</B>f"),
+            [
+                span("a", true, false, false),
+                span("bcd", true, true, false),
+                span("e", true, false, false),
+                span("f", false, false, false),
+            ]
+        );
+        assert_eq!(
+            styles("<u>x<u>y</u>z</u>w</u></u>v"),
+            [
+                span("xyz", false, false, true),
+                span("wv", false, false, false)
+            ]
+        );
+        // Unknown tags keep their content in the surrounding style
+        assert_eq!(
+            styles("<i>a<span>b</span></i>"),
+            [span("ab", false, true, false)]
+        );
+    }
+
+    #[test]
+    fn links() {
+        let spans = RichText::from_markup(
+            "<a href=\"https://a.example\">go <b>now</b></a> then \
+             <a href='outer'><a href=\"inner\">in</a>out</a>",
+        )
+        .spans;
+
+        let links = spans
+            .iter()
+            .map(|span| (span.text.as_str(), span.link.as_deref(), span.underline))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            [
+                ("go ", Some("https://a.example"), true),
+                ("now", Some("https://a.example"), true),
+                (" then ", None, false),
+                ("in", Some("inner"), true),
+                ("out", Some("outer"), true),
+            ]
+        );
+        assert_eq!(spans[1].weight, glyphon::Weight::BOLD);
+    }
+
+    #[test]
+    fn images() {
+        let spans = RichText::from_markup(
+            "see <img src=\"a.png\" alt=\"[a]\"/><img alt='[b]' src='b.png'> \
+             <img src=\"c.png\"/><a href=\"l\"><img alt=\"[d]\" src=\"d.png\"/></a>",
+        )
+        .spans;
+
+        let images = spans
+            .iter()
+            .map(|span| {
+                (
+                    span.text.as_str(),
+                    span.image.as_deref(),
+                    span.link.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Images without alternative text leave nothing to draw
+        assert_eq!(
+            images,
+            [
+                ("see ", None, None),
+                ("[a]", Some("a.png"), None),
+                ("[b]", Some("b.png"), None),
+                (" ", None, None),
+                ("[d]", Some("d.png"), Some("l")),
+            ]
+        );
+        assert!(spans[4].underline);
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            RichText::from_markup("&lt;&gt;&amp;&quot;&apos; &#65;&#x42;&#X43;").text(),
+            "<>&\"' ABC"
+        );
+        // Unknown and invalid entities are kept as they are
+        assert_eq!(
+            RichText::from_markup("&nbsp; &bogus; a & b &#xZZ; &#1114112; &amp").text(),
+            "&nbsp; &bogus; a & b &#xZZ; &#1114112; &amp"
+        );
+        // Attributes are decoded too
+        let spans = RichText::from_markup("<a href=\"?a=1&amp;b=&#50;\">x</a>").spans;
+        assert_eq!(spans[0].link.as_deref(), Some("?a=1&b=2"));
+    }
+
+    #[test]
+    fn attribute_quoting() {
+        assert_eq!(
+            attribute(r#"title='say "hi"' href = "x""#, "href").as_deref(),
+            Some("x")
+        );
+        assert_eq!(
+            attribute(r#"href='say "hi"'"#, "HREF").as_deref(),
+            Some("say \"hi\"")
+        );
+        assert_eq!(attribute(r#"alt="it's""#, "alt").as_deref(), Some("it's"));
+        // Unquoted or unterminated values aren't read
+        assert_eq!(attribute("href=x", "href"), None);
+        assert_eq!(attribute("href='x", "href"), None);
+
+        // A link without a readable target still links
+        let spans = RichText::from_markup("<a href=x>y</a>").spans;
+        assert_eq!(spans[0].link.as_deref(), Some(""));
+    }
+}
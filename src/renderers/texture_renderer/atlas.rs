@@ -0,0 +1,359 @@
+/// Region of the texture array an image was packed into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Allocation {
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Shelf heights are rounded up to this so images of similar height can
+/// share a shelf.
+const SHELF_GRANULARITY: u32 = 8;
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    cursor: u32,
+    // Gaps left behind by deallocations as (x, width), sorted by x
+    free: Vec<(u32, u32)>,
+    allocations: u32,
+}
+
+impl Shelf {
+    fn allocate(&mut self, width: u32, layer_width: u32) -> Option<u32> {
+        if let Some(i) = self.free.iter().position(|(_, w)| *w >= width) {
+            let (x, w) = self.free[i];
+            if w == width {
+                self.free.remove(i);
+            } else {
+                self.free[i] = (x + width, w - width);
+            }
+
+            self.allocations += 1;
+            return Some(x);
+        }
+
+        if self.cursor + width > layer_width {
+            return None;
+        }
+
+        let x = self.cursor;
+        self.cursor += width;
+        self.allocations += 1;
+
+        Some(x)
+    }
+
+    fn deallocate(&mut self, x: u32, width: u32) {
+        self.allocations -= 1;
+
+        if self.allocations == 0 {
+            self.free.clear();
+            self.cursor = 0;
+            return;
+        }
+
+        let i = self.free.partition_point(|(fx, _)| *fx < x);
+        self.free.insert(i, (x, width));
+
+        // Merge with the gap after, then with the gap before
+        if i + 1 < self.free.len() && x + width == self.free[i + 1].0 {
+            let (_, next) = self.free.remove(i + 1);
+            self.free[i].1 += next;
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == x {
+            let (_, gap) = self.free.remove(i);
+            self.free[i - 1].1 += gap;
+        }
+
+        // A gap touching the cursor just gives the space back to the shelf
+        if let Some(&(fx, fw)) = self.free.last()
+            && fx + fw == self.cursor
+        {
+            self.cursor = fx;
+            self.free.pop();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Layer {
+    shelves: Vec<Shelf>,
+    cursor: u32,
+}
+
+/// Shelf packer that places many images into each layer of a texture array.
+///
+/// Every layer is split into horizontal shelves. An image goes into the
+/// tightest shelf that is tall enough. When none fits, the tightest empty
+/// shelf is cut down to the image's height, or else a new shelf is opened
+/// at the bottom of the first layer with room left.
+pub(super) struct Atlas {
+    width: u32,
+    height: u32,
+    layers: Vec<Layer>,
+}
+
+impl Atlas {
+    pub fn new(width: u32, height: u32, layers: u32) -> Self {
+        Self {
+            width,
+            height,
+            layers: (0..layers).map(|_| Layer::default()).collect(),
+        }
+    }
+
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<Allocation> {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        if width > self.width || height > self.height {
+            return None;
+        }
+
+        let shelf_height = height.next_multiple_of(SHELF_GRANULARITY).min(self.height);
+
+        // Prefer the tightest existing shelf that doesn't waste more than half its height
+        let existing = self
+            .layers
+            .iter()
+            .enumerate()
+            .flat_map(|(l, layer)| {
+                layer
+                    .shelves
+                    .iter()
+                    .enumerate()
+                    .map(move |(s, shelf)| (l, s, shelf))
+            })
+            .filter(|(_, _, shelf)| shelf.allocations > 0)
+            .filter(|(_, _, shelf)| shelf.height >= height && shelf.height <= shelf_height * 2)
+            .filter(|(_, _, shelf)| {
+                shelf.cursor + width <= self.width || shelf.free.iter().any(|(_, w)| *w >= width)
+            })
+            .min_by_key(|(_, _, shelf)| shelf.height)
+            .map(|(l, s, _)| (l, s));
+
+        let (l, s) = match existing.or_else(|| self.reuse_empty_shelf(shelf_height)) {
+            Some(found) => found,
+            None => {
+                let (l, layer) = self
+                    .layers
+                    .iter_mut()
+                    .enumerate()
+                    .find(|(_, layer)| layer.cursor + shelf_height <= self.height)?;
+
+                layer.shelves.push(Shelf {
+                    y: layer.cursor,
+                    height: shelf_height,
+                    cursor: 0,
+                    free: Vec::new(),
+                    allocations: 0,
+                });
+                layer.cursor += shelf_height;
+
+                (l, layer.shelves.len() - 1)
+            }
+        };
+
+        let shelf = &mut self.layers[l].shelves[s];
+        let x = shelf.allocate(width, self.width)?;
+
+        Some(Allocation {
+            layer: l as u32,
+            x,
+            y: shelf.y,
+            width,
+            height,
+        })
+    }
+
+    /// Cuts the tightest empty shelf at least `height` tall down to it,
+    /// leaving the rest as an empty shelf below.
+    fn reuse_empty_shelf(&mut self, height: u32) -> Option<(usize, usize)> {
+        let (l, s) = self
+            .layers
+            .iter()
+            .enumerate()
+            .flat_map(|(l, layer)| {
+                layer
+                    .shelves
+                    .iter()
+                    .enumerate()
+                    .map(move |(s, shelf)| (l, s, shelf))
+            })
+            .filter(|(_, _, shelf)| shelf.allocations == 0 && shelf.height >= height)
+            .min_by_key(|(_, _, shelf)| shelf.height)
+            .map(|(l, s, _)| (l, s))?;
+
+        let shelves = &mut self.layers[l].shelves;
+        let rest = shelves[s].height - height;
+        if rest > 0 {
+            shelves[s].height = height;
+            shelves.insert(
+                s + 1,
+                Shelf {
+                    y: shelves[s].y + height,
+                    height: rest,
+                    cursor: 0,
+                    free: Vec::new(),
+                    allocations: 0,
+                },
+            );
+        }
+
+        Some((l, s))
+    }
+
+    pub fn deallocate(&mut self, allocation: &Allocation) {
+        let Some(layer) = self.layers.get_mut(allocation.layer as usize) else {
+            return;
+        };
+
+        let Some(s) = layer
+            .shelves
+            .iter()
+            .position(|shelf| shelf.y == allocation.y)
+        else {
+            return;
+        };
+
+        let shelf = &mut layer.shelves[s];
+        shelf.deallocate(allocation.x, allocation.width);
+
+        // Empty shelves next to each other become one, so that the band
+        // can be cut up again for images of any height
+        if shelf.allocations == 0 {
+            if layer
+                .shelves
+                .get(s + 1)
+                .is_some_and(|next| next.allocations == 0)
+            {
+                let next = layer.shelves.remove(s + 1);
+                layer.shelves[s].height += next.height;
+            }
+            if s > 0 && layer.shelves[s - 1].allocations == 0 {
+                let shelf = layer.shelves.remove(s);
+                layer.shelves[s - 1].height += shelf.height;
+            }
+        }
+
+        // Empty shelves at the bottom of a layer are released so that
+        // the space can be reused for shelves of a different height
+        while layer
+            .shelves
+            .last()
+            .is_some_and(|shelf| shelf.allocations == 0)
+        {
+            layer.shelves.pop();
+        }
+        layer.cursor = layer
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &Allocation, b: &Allocation) -> bool {
+        a.layer == b.layer
+            && a.x < b.x + b.width
+            && b.x < a.x + a.width
+            && a.y < b.y + b.height
+            && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn packs_without_overlapping() {
+        let mut atlas = Atlas::new(64, 64, 2);
+
+        let allocations = [(20, 10), (20, 12), (30, 16), (64, 30), (10, 5), (40, 40)]
+            .map(|(width, height)| atlas.allocate(width, height).unwrap());
+
+        // Images of similar height share a shelf
+        assert_eq!(allocations[0].y, allocations[1].y);
+        assert_eq!(allocations[1].x, 20);
+
+        allocations.iter().enumerate().for_each(|(i, a)| {
+            assert!(a.x + a.width <= 64 && a.y + a.height <= 64);
+            assert!(allocations[i + 1..].iter().all(|b| !overlaps(a, b)));
+        });
+
+        // Too large for a layer
+        assert_eq!(atlas.allocate(65, 1), None);
+    }
+
+    #[test]
+    fn fills_layers() {
+        let mut atlas = Atlas::new(32, 32, 2);
+
+        let layers = (0..8)
+            .map(|_| atlas.allocate(32, 8).unwrap().layer)
+            .collect::<Vec<_>>();
+        assert_eq!(layers, [0, 0, 0, 0, 1, 1, 1, 1]);
+
+        assert_eq!(atlas.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn reuses_freed_space() {
+        let mut atlas = Atlas::new(32, 32, 1);
+
+        let row = (0..4)
+            .map(|_| atlas.allocate(8, 8).unwrap())
+            .collect::<Vec<_>>();
+        let below = atlas.allocate(32, 24).unwrap();
+        assert_eq!(atlas.allocate(8, 8), None);
+
+        // A gap in the middle of a shelf
+        atlas.deallocate(&row[1]);
+        assert_eq!(atlas.allocate(8, 8), Some(row[1]));
+
+        // Merged gaps fit a wider image
+        atlas.deallocate(&row[1]);
+        atlas.deallocate(&row[2]);
+        let wide = atlas.allocate(16, 8).unwrap();
+        assert_eq!((wide.x, wide.y), (8, row[0].y));
+
+        // An empty shelf at the bottom can be reopened with another height
+        atlas.deallocate(&below);
+        let tall = atlas.allocate(16, 20).unwrap();
+        assert_eq!(tall.y, below.y);
+    }
+
+    #[test]
+    fn reuses_empty_shelves_in_the_middle() {
+        let mut atlas = Atlas::new(32, 64, 1);
+
+        let top = atlas.allocate(32, 8).unwrap();
+        let tall = atlas.allocate(32, 24).unwrap();
+        let taller = atlas.allocate(32, 16).unwrap();
+        let bottom = atlas.allocate(32, 16).unwrap();
+        assert_eq!(atlas.allocate(1, 1), None);
+
+        // Adjacent empty shelves merge into a band of 40 pixels, which is
+        // cut up for images of other heights
+        atlas.deallocate(&tall);
+        atlas.deallocate(&taller);
+        let small = (0..5)
+            .map(|_| atlas.allocate(32, 8).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            small.iter().map(|a| a.y).collect::<Vec<_>>(),
+            [8, 16, 24, 32, 40]
+        );
+        assert_eq!(atlas.allocate(1, 1), None);
+
+        // And merged again once they're freed
+        small.iter().for_each(|a| atlas.deallocate(a));
+        let wide = atlas.allocate(32, 40).unwrap();
+        assert_eq!(wide.y, 8);
+
+        [top, wide, bottom].iter().for_each(|a| atlas.deallocate(a));
+        assert_eq!(atlas.allocate(32, 64).unwrap().y, 0);
+    }
+}
@@ -1,81 +1,56 @@
 use crate::buffers::{self, DataDescription, GpuBuffer};
+use crate::clip::{ClipBuffer, ClipId, ClipStack};
+use crate::renderers::blur::{BlurLevels, blur_level};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct BlurInstance {
-    pub blur_sigma: u32,
+    /// `[first, count]` of the weights and offsets of the horizontal and
+    /// then the vertical kernel, where no weights pass the texture through
+    /// along that axis.
+    pub kernels: [u32; 4],
     pub blur_color: [f32; 4],
     pub rect: [f32; 4],
     pub scale: [f32; 2],
+    pub depth: f32,
+    /// The blur runs at `1 / 2^level` of the target's resolution.
+    pub level: u32,
+    pub clip: ClipId,
 }
 
 impl DataDescription for BlurInstance {
     const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
-    const ATTRIBS: &'static [wgpu::VertexAttribute] =
-        &wgpu::vertex_attr_array![2 => Uint32, 3 => Float32x4, 4 => Float32x4, 5 => Float32x2];
+    const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        2 => Uint32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x2,
+        6 => Float32,
+        7 => Uint32,
+        8 => Uint32x2,
+    ];
 }
 
 impl buffers::instance::Instance for BlurInstance {}
 
-fn gaussian_kernel_1d(radius: i32, sigma: f32) -> (Vec<f32>, Vec<f32>) {
-    use std::f32::consts::PI;
-
-    let mut k_values = Vec::with_capacity((2 * radius + 1) as usize);
-    let mut offsets = Vec::with_capacity((2 * radius + 1) as usize);
-    let mut intensity = 0.0;
-
-    for y in -radius..=radius {
-        let y_f = y as f32;
-        let g =
-            1.0 / (2.0 * PI * sigma * sigma).sqrt() * (-y_f * y_f / (2.0 * sigma * sigma)).exp();
-        k_values.push(g);
-        offsets.push(y_f);
-        intensity += g;
-    }
-
-    let mut final_k_values = Vec::new();
-    let mut final_offsets = Vec::new();
-
-    let mut i = 0;
-    while i + 1 < k_values.len() {
-        let a = k_values[i];
-        let b = k_values[i + 1];
-        let k = a + b;
-        let alpha = a / k;
-        let offset = offsets[i] + alpha;
-        final_k_values.push(k / intensity);
-        final_offsets.push(offset);
-        i += 2;
-    }
-
-    if i < k_values.len() {
-        let a = k_values[i];
-        let offset = offsets[i];
-        final_k_values.push(a / intensity);
-        final_offsets.push(offset);
-    }
-
-    (final_k_values, final_offsets)
-}
-
-type StorageBuffers = (
-    buffers::StorageBuffer<[u32; 2]>,
-    buffers::StorageBuffer<f32>,
-    buffers::StorageBuffer<f32>,
-);
-
 pub struct BlurRenderer {
     pub pipelines: Pipelines,
-    pub intermediate_view: wgpu::TextureView,
-    pub output_view: wgpu::TextureView,
     pub instance_buffer: buffers::instance::InstanceBuffer<BlurInstance>,
-    bind_group_layout: wgpu::BindGroupLayout,
-    bind_groups: Option<[wgpu::BindGroup; 2]>,
-    storage_buffers: Option<StorageBuffers>,
-    sampler: wgpu::Sampler,
+    levels: BlurLevels,
+    /// Level of each prepared instance.
+    instance_levels: Vec<u32>,
+    /// `[left, top, right, bottom]` pixels of the content each prepared
+    /// instance needs at its level.
+    instance_regions: Vec<[f32; 4]>,
+    clips: ClipBuffer,
 }
 
 impl BlurRenderer {
+    /// Where textures are drawn before they're blurred.
+    pub fn intermediate_view(&self) -> &wgpu::TextureView {
+        self.levels.source_view()
+    }
+
     pub fn resize(
         &mut self,
         device: &wgpu::Device,
@@ -83,43 +58,7 @@ impl BlurRenderer {
         height: u32,
         format: wgpu::TextureFormat,
     ) {
-        let intermediate_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("horizontal_blur_texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
-        self.intermediate_view = intermediate_texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            ..Default::default()
-        });
-
-        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("vertical_blur_texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
-        self.output_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            ..Default::default()
-        });
+        self.levels.resize(device, width, height, format);
     }
 
     pub fn new(
@@ -130,57 +69,7 @@ impl BlurRenderer {
     ) -> Self {
         let buffers = [buffers::Vertex::desc(), BlurInstance::desc()];
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 4,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
+        let levels = BlurLevels::new(device, format, width, height);
 
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -197,238 +86,192 @@ impl BlurRenderer {
                 label: Some("uniform_bind_group_layout"),
             });
 
+        let clips = ClipBuffer::new(device);
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&bind_group_layout, &uniform_bind_group_layout],
+            bind_group_layouts: &[
+                levels.bind_group_layout(),
+                &uniform_bind_group_layout,
+                clips.bind_group_layout(),
+            ],
             immediate_size: 0,
         });
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("blur_shader"),
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
-                "shader.wgsl"
-            ))),
-        });
-
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
-
-        let intermediate_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("horizontal_blur_texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
-        let intermediate_view = intermediate_texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            ..Default::default()
-        });
-
-        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("vertical_blur_texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
-        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            ..Default::default()
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}{}{}",
+                    include_str!("shader.wgsl"),
+                    BlurLevels::SHADER,
+                    ClipBuffer::SHADER
+                )
+                .into(),
+            ),
         });
 
         Self {
-            storage_buffers: None,
-            bind_group_layout,
-            sampler,
             pipelines: Pipelines::new(device, &pipeline_layout, &shader, &buffers, format),
-            bind_groups: None,
-            intermediate_view,
-            output_view,
+            levels,
+            instance_levels: Vec::new(),
+            instance_regions: Vec::new(),
             instance_buffer: buffers::instance::InstanceBuffer::new(device, &[]),
+            clips,
         }
     }
 
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.clips.prepare(device, queue, clips);
+    }
+
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        textures: &[super::TextureArea],
+        textures: &[&super::TextureArea],
     ) {
-        let (metadata, weights, offsets) = textures.iter().fold(
-            (Vec::new(), Vec::new(), Vec::new()),
-            |(mut metadata, mut weights, mut offsets), texture| {
-                let (mut local_weights, mut local_offsets) = gaussian_kernel_1d(
-                    (texture.buffer.filters.blur * 3) as i32,
-                    texture.buffer.filters.blur as f32,
-                );
-                metadata.push([texture.buffer.filters.blur, weights.len() as u32]);
-                weights.append(&mut local_weights);
-                offsets.append(&mut local_offsets);
-                (metadata, weights, offsets)
-            },
-        );
-
-        // Always create storage buffers, even if empty, to avoid shader validation errors
-        let metadata = if metadata.is_empty() {
-            buffers::StorageBuffer::new(device, &[[0u32, 0u32]])
-        } else {
-            buffers::StorageBuffer::new(device, &metadata)
-        };
-
-        let weights = if weights.is_empty() {
-            buffers::StorageBuffer::new(device, &[0.0f32])
-        } else {
-            buffers::StorageBuffer::new(device, &weights)
-        };
-
-        let offsets = if offsets.is_empty() {
-            buffers::StorageBuffer::new(device, &[0.0f32])
-        } else {
-            buffers::StorageBuffer::new(device, &offsets)
-        };
-
-        self.bind_groups = Some([
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &self.bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&self.intermediate_view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&self.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: metadata.buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: weights.buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 4,
-                        resource: offsets.buffer.as_entire_binding(),
-                    },
-                ],
-                label: Some("horizontal_blur_bg"),
-            }),
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &self.bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&self.output_view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&self.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: metadata.buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: weights.buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 4,
-                        resource: offsets.buffer.as_entire_binding(),
-                    },
-                ],
-                label: Some("vertical_blur_bg"),
-            }),
-        ]);
+        self.instance_levels.clear();
+        self.instance_regions.clear();
 
-        self.storage_buffers = Some((metadata, weights, offsets));
+        // Nothing to blur, which leaves the buffers of the last blur alone
+        if textures.is_empty() {
+            return;
+        }
 
-        let instances = textures
+        let sigmas = textures
             .iter()
-            .map(|texture| {
-                let width = texture.buffer.width;
-                let height = texture.buffer.height;
+            .map(|texture| blur_level(texture.buffer.filters.blur))
+            .collect::<Vec<_>>();
+
+        self.levels
+            .prepare_kernels(device, queue, sigmas.iter().flat_map(|(_, sigma)| *sigma));
 
+        let instances = textures
+            .iter()
+            .zip(sigmas)
+            .map(|(texture, (level, [sigma_x, sigma_y]))| {
+                let [x_first, x_count] = self.levels.kernel(sigma_x);
+                let [y_first, y_count] = self.levels.kernel(sigma_y);
+                // What the horizontal pass draws, and the texels its kernel
+                // reads to the sides of that
+                let rect = texture.covered_rect();
+                let [scale_x, scale_y] = texture.buffer.scale;
+                let factor = (1 << level) as f32;
+                let reach = factor * (2. + (sigma_x * 3.).ceil());
+                self.instance_regions.push([
+                    rect[0] * scale_x - reach,
+                    rect[1] * scale_y - factor,
+                    (rect[0] + rect[2]) * scale_x + reach,
+                    (rect[1] + rect[3]) * scale_y + factor,
+                ]);
+
+                self.instance_levels.push(level);
                 BlurInstance {
-                    blur_sigma: texture.buffer.filters.blur,
+                    kernels: [x_first, x_count, y_first, y_count],
                     blur_color: texture.buffer.filters.blur_color,
-                    rect: [texture.left, texture.top, width, height],
+                    rect,
                     scale: texture.buffer.scale,
+                    depth: texture.depth,
+                    level,
+                    clip: texture.clip,
                 }
             })
             .collect::<Vec<_>>();
 
-        // Always create at least one instance for passthrough rendering
-        let instances_to_use = if instances.is_empty() {
-            vec![BlurInstance {
-                blur_sigma: 0,
-                blur_color: [0.0, 0.0, 0.0, 0.0],
-                rect: [0.0, 0.0, 0.0, 0.0],
-                scale: [1.0, 1.0],
-            }]
-        } else {
-            instances
-        };
-
-        let instance_buffer_size = std::mem::size_of::<BlurInstance>() * instances_to_use.len();
-
-        if self.instance_buffer.size() < instance_buffer_size as u32 {
-            self.instance_buffer =
-                buffers::instance::InstanceBuffer::with_size(device, instance_buffer_size as u64);
+        let instance_buffer_size = std::mem::size_of_val(instances.as_slice()) as u64;
+
+        if self.instance_buffer.capacity() < instance_buffer_size {
+            self.instance_buffer = buffers::instance::InstanceBuffer::with_size(
+                device,
+                instance_buffer_size.max(self.instance_buffer.capacity() * 2),
+            );
         }
 
-        self.instance_buffer.write(queue, &instances_to_use);
+        self.instance_buffer.write(queue, &instances);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &self,
         output_texture_view: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
         encoder: &mut wgpu::CommandEncoder,
         viewport: &crate::viewport::Viewport,
         vertex_buffer: &buffers::VertexBuffer,
         index_buffer: &buffers::IndexBuffer,
+        instances: std::ops::Range<u32>,
     ) {
-        let horizontal_bg = &self.bind_groups.as_ref().unwrap()[0];
-        let vertical_bg = &self.bind_groups.as_ref().unwrap()[1];
+        // Consecutive instances that blur at the same level
+        let mut runs = Vec::new();
+        let mut start = instances.start;
+        self.instance_levels[instances.start as usize..instances.end as usize]
+            .chunk_by(|a, b| a == b)
+            .for_each(|chunk| {
+                let end = start + chunk.len() as u32;
+                runs.push((chunk[0], start..end));
+                start = end;
+            });
 
-        let mut horizontal_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &self.output_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                    store: wgpu::StoreOp::Store,
+        // Levels that are only passed through to are skipped
+        let max_level = runs.iter().map(|(level, _)| *level).max().unwrap_or(0);
+
+        // Only the region the instances read from is downsampled
+        let region = self.instance_regions[instances.start as usize..instances.end as usize]
+            .iter()
+            .fold(
+                [
+                    f32::INFINITY,
+                    f32::INFINITY,
+                    f32::NEG_INFINITY,
+                    f32::NEG_INFINITY,
+                ],
+                |[left, top, right, bottom], region| {
+                    [
+                        left.min(region[0]),
+                        top.min(region[1]),
+                        right.max(region[2]),
+                        bottom.max(region[3]),
+                    ]
                 },
-                depth_slice: None,
-            })],
-            ..Default::default()
-        });
+            );
+        self.levels.downsample(encoder, max_level, region);
+
+        (0..=max_level).for_each(|level| {
+            if !runs.iter().any(|(l, _)| *l == level) {
+                return;
+            }
+
+            let mut horizontal_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blur_horizontal_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.levels.horizontal_view(level),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
 
-        horizontal_pass.set_pipeline(&self.pipelines.horizontal);
-        horizontal_pass.set_bind_group(0, horizontal_bg, &[]);
-        horizontal_pass.set_bind_group(1, &viewport.bind_group, &[]);
-        horizontal_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-        horizontal_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        horizontal_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        horizontal_pass.draw_indexed(0..index_buffer.size(), 0, 0..self.instance_buffer.size());
-        drop(horizontal_pass);
+            horizontal_pass.set_pipeline(&self.pipelines.horizontal);
+            horizontal_pass.set_bind_group(0, &self.levels.bind_groups(level)[0], &[]);
+            horizontal_pass.set_bind_group(1, &viewport.bind_group, &[]);
+            horizontal_pass.set_bind_group(2, self.clips.bind_group(), &[]);
+            horizontal_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            horizontal_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            horizontal_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            runs.iter()
+                .filter(|(l, _)| *l == level)
+                .for_each(|(_, range)| {
+                    horizontal_pass.draw_indexed(0..index_buffer.size(), 0, range.clone())
+                });
+        });
 
         let mut vertical_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("blur_vertical_pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: output_texture_view,
                 resolve_target: None,
@@ -438,22 +281,40 @@ impl BlurRenderer {
                 },
                 depth_slice: None,
             })],
+            depth_stencil_attachment: depth_view.map(|view| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
             ..Default::default()
         });
 
-        vertical_pass.set_pipeline(&self.pipelines.vertical);
-        vertical_pass.set_bind_group(0, vertical_bg, &[]);
+        vertical_pass.set_pipeline(match depth_view {
+            Some(_) => &self.pipelines.vertical_depth,
+            None => &self.pipelines.vertical,
+        });
         vertical_pass.set_bind_group(1, &viewport.bind_group, &[]);
+        vertical_pass.set_bind_group(2, self.clips.bind_group(), &[]);
         vertical_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
         vertical_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
         vertical_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        vertical_pass.draw_indexed(0..index_buffer.size(), 0, 0..self.instance_buffer.size());
+        runs.into_iter().for_each(|(level, range)| {
+            vertical_pass.set_bind_group(0, &self.levels.bind_groups(level)[1], &[]);
+            vertical_pass.draw_indexed(0..index_buffer.size(), 0, range);
+        });
     }
 }
 
 pub struct Pipelines {
     pub horizontal: wgpu::RenderPipeline,
     pub vertical: wgpu::RenderPipeline,
+    /// Composites like `vertical`, but takes part in a depth buffer.
+    pub vertical_depth: wgpu::RenderPipeline,
 }
 
 impl Pipelines {
@@ -464,63 +325,67 @@ impl Pipelines {
         buffers: &[wgpu::VertexBufferLayout; 2],
         format: wgpu::TextureFormat,
     ) -> Self {
+        let create_pipeline =
+            |label, layout, vertex_entry_point, entry_point, buffers, blend, depth_stencil| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(layout),
+                    vertex: wgpu::VertexState {
+                        module: shader,
+                        entry_point: Some(vertex_entry_point),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        buffers,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader,
+                        entry_point: Some(entry_point),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend,
+                            write_mask: wgpu::ColorWrites::default(),
+                        })],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleStrip,
+                        ..Default::default()
+                    },
+                    depth_stencil,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                })
+            };
+
+        let blend = Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
         Self {
-            horizontal: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("horizontal blur pipeline"),
-                layout: Some(pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: shader,
-                    entry_point: Some("vs_main"),
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: shader,
-                    entry_point: Some("fs_horizontal_blur"),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format,
-                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
-                        write_mask: wgpu::ColorWrites::default(),
-                    })],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview_mask: None,
-                cache: None,
-            }),
-            vertical: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("vertical blur pipeline"),
-                layout: Some(pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: shader,
-                    entry_point: Some("vs_main"),
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: shader,
-                    entry_point: Some("fs_vertical_blur"),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format,
-                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
-                        write_mask: wgpu::ColorWrites::default(),
-                    })],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                cache: None,
-                multiview_mask: None,
-            }),
+            horizontal: create_pipeline(
+                "horizontal blur pipeline",
+                pipeline_layout,
+                "vs_horizontal",
+                "fs_horizontal_blur",
+                buffers.as_slice(),
+                blend,
+                None,
+            ),
+            vertical: create_pipeline(
+                "vertical blur pipeline",
+                pipeline_layout,
+                "vs_vertical",
+                "fs_vertical_blur",
+                buffers.as_slice(),
+                blend,
+                None,
+            ),
+            vertical_depth: create_pipeline(
+                "vertical blur depth pipeline",
+                pipeline_layout,
+                "vs_vertical",
+                "fs_vertical_blur",
+                buffers.as_slice(),
+                blend,
+                Some(super::depth_stencil_state()),
+            ),
         }
     }
 }
@@ -12,108 +12,76 @@ struct VertexInput {
 };
 
 struct InstanceInput {
-    @location(2) blur_sigma: u32,
+    @location(2) kernels: vec4<u32>,  // [first, count] of the horizontal and vertical weights and offsets
     @location(3) blur_color: vec4<f32>,
     @location(4) rect: vec4<f32>,
     @location(5) scale: vec2<f32>,
+    @location(6) depth: f32,
+    @location(7) level: u32,
+    @location(8) clip: vec2<u32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) blur_sigma: u32,
+    @location(0) kernels: vec4<u32>,
     @location(1) tex_coords: vec2<f32>,
-    @location(2) screen_size: vec2<f32>,
-    @location(3) blur_color: vec4<f32>,
+    @location(2) blur_color: vec4<f32>,
+    @location(3) @interpolate(flat) clip: vec2<u32>,  // [first, count] of the clip rects
 };
 
-@vertex
-fn vs_main(
-    model: VertexInput,
-    instance: InstanceInput,
-    @builtin(instance_index) instance_idx: u32,
-) -> VertexOutput {
+fn vertex(model: VertexInput, instance: InstanceInput, grow: f32) -> VertexOutput {
     var out: VertexOutput;
 
     let rect = instance.rect;
     let screen_res = vec2<f32>(params.screen_resolution);
 
-    let pos = vec2<f32>(rect.x, rect.y) * instance.scale;
-    let size = vec2<f32>(rect.z, rect.w) * instance.scale;
-    let local_pos = (model.position - vec2<f32>(0.5)) * size;
-    let position = local_pos + pos + size * 0.5;
+    let pos = vec2<f32>(rect.x, rect.y) * instance.scale - grow;
+    let size = vec2<f32>(rect.z, rect.w) * instance.scale + grow * 2.0;
+    let position = pos + model.position * size;
 
     let ndc = (position / screen_res) * 2.0 - vec2<f32>(1.0, 1.0);
     let ndc_fixed = vec2<f32>(ndc.x, -ndc.y);
 
-    out.clip_position = vec4<f32>(ndc_fixed, 0.0, 1.0);
+    out.clip_position = vec4<f32>(ndc_fixed, instance.depth, 1.0);
     out.tex_coords = position / screen_res;
-    out.screen_size = screen_res;
-    out.blur_sigma = instance.blur_sigma;
+    out.kernels = instance.kernels;
     out.blur_color = instance.blur_color;
+    out.clip = instance.clip;
 
     return out;
 }
 
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>; 
-@group(0) @binding(1)
-var s_diffuse: sampler;
-@group(0) @binding(2)
-var<storage, read> metadata: array<vec2<u32>>;
-@group(0) @binding(3)
-var<storage, read> weights: array<f32>;
-@group(0) @binding(4)
-var<storage, read> offsets: array<f32>;
-
-fn find_blur_metadata(blur_sigma: u32) -> u32 {
-    for (var i: u32 = 0; i < arrayLength(&metadata); i++) {
-        if metadata[i].x == blur_sigma {
-            return metadata[i].y;
-        }
-    }
+@vertex
+fn vs_horizontal(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    // A texel around the rect, so that the vertical pass can filter the
+    // edges of downsampled levels
+    return vertex(model, instance, f32(1u << instance.level));
+}
 
-    return 0;
+@vertex
+fn vs_vertical(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    return vertex(model, instance, 0.0);
 }
 
 @fragment
 fn fs_horizontal_blur(in: VertexOutput) -> @location(0) vec4<f32> {
-    let tex_coords = in.tex_coords;
-
-    if in.blur_sigma == 0 {
-        return textureSample(t_diffuse, s_diffuse, tex_coords);
-    }
-
-    let metadata = find_blur_metadata(in.blur_sigma);
-
-    var color: vec4<f32> = in.blur_color;
-    for (var i: u32 = metadata; i < in.blur_sigma * 3; i++) {
-        let offset = offsets[i];
-        let weight = weights[i];
-        let tex_offset = vec2<f32>(offset / in.screen_size.x, 0.0);
-        let sample_coord = tex_coords + tex_offset;
-        color   += textureSample(t_diffuse, s_diffuse, sample_coord) * weight;
-    }
-
-    return color;
+    return blur(in.tex_coords, in.kernels.xy, vec2<f32>(1.0, 0.0), in.blur_color);
 }
 
 @fragment
 fn fs_vertical_blur(in: VertexOutput) -> @location(0) vec4<f32> {
-    let tex_coords = in.tex_coords;
-
-    if in.blur_sigma == 0 {
-        return textureSample(t_diffuse, s_diffuse, tex_coords);
-    }
-
-    let metadata = find_blur_metadata(in.blur_sigma);
+    var color = blur(in.tex_coords, in.kernels.zw, vec2<f32>(0.0, 1.0), in.blur_color);
+    color *= clip_coverage(in.clip_position.xy, in.clip);
 
-    var color: vec4<f32> = in.blur_color;
-    for (var i: u32 = metadata; i < in.blur_sigma * 3; i++) {
-        let offset = offsets[i];
-        let weight = weights[i];
-        let tex_offset = vec2<f32>(0.0, offset / in.screen_size.y);
-        let sample_coord = tex_coords + tex_offset;
-        color   += textureSample(t_diffuse, s_diffuse, sample_coord) * weight;
+    // Keep empty pixels out of the depth buffer
+    if color.a < 0.001 {
+        discard;
     }
 
     return color;
@@ -0,0 +1,159 @@
+use super::atlas::{Allocation, Atlas};
+use std::collections::HashMap;
+
+/// Stable handle to an image uploaded with
+/// [`TextureRenderer::upload`](super::TextureRenderer::upload).
+///
+/// Handles are never reused, so a handle whose image has been evicted
+/// simply stops resolving instead of pointing at another image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureId(u64);
+
+struct Entry {
+    allocation: Allocation,
+    last_used: u64,
+}
+
+/// Maps texture handles to regions of the atlas and evicts the least
+/// recently used images once the atlas is full.
+pub(super) struct TextureCache {
+    atlas: Atlas,
+    entries: HashMap<TextureId, Entry>,
+    transient: Vec<TextureId>,
+    next_id: u64,
+    frame: u64,
+}
+
+impl TextureCache {
+    pub fn new(width: u32, height: u32, layers: u32) -> Self {
+        Self {
+            atlas: Atlas::new(width, height, layers),
+            entries: HashMap::new(),
+            transient: Vec::new(),
+            next_id: 0,
+            frame: 0,
+        }
+    }
+
+    /// Starts a new frame and releases the regions of last frame's
+    /// transient uploads.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+        std::mem::take(&mut self.transient)
+            .into_iter()
+            .for_each(|id| {
+                self.remove(id);
+            });
+    }
+
+    /// Reserves a region for a new image. Images used during the current
+    /// frame are never evicted, so this fails only when they alone fill
+    /// the atlas or the image is larger than a layer.
+    pub fn insert(&mut self, width: u32, height: u32) -> Option<(TextureId, Allocation)> {
+        let allocation = loop {
+            match self.atlas.allocate(width, height) {
+                Some(allocation) => break allocation,
+                None => self.evict()?,
+            }
+        };
+
+        let id = TextureId(self.next_id);
+        self.next_id += 1;
+        self.entries.insert(
+            id,
+            Entry {
+                allocation,
+                last_used: self.frame,
+            },
+        );
+
+        Some((id, allocation))
+    }
+
+    /// Like [`insert`](Self::insert), but the region is released again at
+    /// the start of the next frame.
+    pub fn insert_transient(&mut self, width: u32, height: u32) -> Option<Allocation> {
+        let (id, allocation) = self.insert(width, height)?;
+        self.transient.push(id);
+
+        Some(allocation)
+    }
+
+    /// Returns the region of `id` and marks it as used in the current frame.
+    pub fn get(&mut self, id: TextureId) -> Option<Allocation> {
+        let entry = self.entries.get_mut(&id)?;
+        entry.last_used = self.frame;
+
+        Some(entry.allocation)
+    }
+
+    pub fn contains(&self, id: TextureId) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    pub fn remove(&mut self, id: TextureId) -> bool {
+        match self.entries.remove(&id) {
+            Some(entry) => {
+                self.atlas.deallocate(&entry.allocation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn evict(&mut self) -> Option<()> {
+        let id = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.last_used < self.frame)
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(id, _)| *id)?;
+
+        self.remove(id);
+
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = TextureCache::new(32, 32, 1);
+
+        let (old, _) = cache.insert(32, 16).unwrap();
+        let (used, _) = cache.insert(32, 16).unwrap();
+
+        cache.begin_frame();
+        cache.begin_frame();
+        cache.get(used).unwrap();
+
+        // The layer is full, so the image not used this frame makes room
+        let (new, _) = cache.insert(32, 16).unwrap();
+        assert!(!cache.contains(old));
+        assert!(cache.contains(used));
+        assert!(cache.contains(new));
+
+        // Images used this frame are never evicted
+        assert_eq!(cache.insert(32, 16), None);
+        assert!(cache.contains(used) && cache.contains(new));
+    }
+
+    #[test]
+    fn transient_entries_expire() {
+        let mut cache = TextureCache::new(32, 32, 1);
+
+        let transient = cache.insert_transient(32, 32).unwrap();
+        assert_eq!(cache.insert(32, 32), None);
+
+        // Released at the start of the next frame only
+        cache.begin_frame();
+        let (id, allocation) = cache.insert(32, 32).unwrap();
+        assert_eq!(allocation, transient);
+
+        cache.begin_frame();
+        assert!(cache.contains(id));
+    }
+}
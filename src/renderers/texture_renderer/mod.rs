@@ -1,19 +1,30 @@
+mod atlas;
 mod blur;
+mod cache;
 
+use crate::backdrop::Backdrop;
 use crate::buffers::{self, DataDescription, GpuBuffer};
+use crate::clip::{ClipBuffer, ClipId, ClipStack};
+pub use cache::TextureId;
+use std::ops::Range;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct TextureInstance {
-    pub filters1: [f32; 4],       // [opacity, brightness, contrast, saturation]
-    pub filters2: [f32; 4],       // [hue_rotate, sepia, invert, grayscale]
-    pub rotation_depth: [f32; 2], // [rotation, depth]
+    pub filters1: [f32; 4],  // [opacity, brightness, contrast, saturation]
+    pub filters2: [f32; 4],  // [hue_rotate, sepia, invert, grayscale]
+    pub transform: [f32; 4], // [a, b, c, d] of `Transforms::matrix`
+    pub translate: [f32; 2], // [e, f] of `Transforms::matrix`
+    pub depth: f32,
     pub scale: [f32; 2],
-    pub skew: [f32; 2],
     pub rect: [f32; 4],
     pub radius: [f32; 4],
-    pub texture_bounds: [f32; 4],
-    pub shadow: [f32; 3],
+    pub texture_bounds: [f32; 4], // UV rect of the image inside its atlas layer
+    pub shadow: [f32; 4],         // [offset_x, offset_y, softness, spread]
+    pub layer: u32,
+    pub clip_bounds: [f32; 4],
+    pub shadow_color: [f32; 4],
+    pub clip: ClipId,
 }
 
 impl DataDescription for TextureInstance {
@@ -22,13 +33,18 @@ impl DataDescription for TextureInstance {
     const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
         1 => Float32x4,
         2 => Float32x4,
-        3 => Float32x2,
+        3 => Float32x4,
         4 => Float32x2,
-        5 => Float32x2,
-        6 => Float32x4,
+        5 => Float32,
+        6 => Float32x2,
         7 => Float32x4,
         8 => Float32x4,
-        9 => Float32x3,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Uint32,
+        12 => Float32x4,
+        13 => Float32x4,
+        14 => Uint32x2,
     ];
 }
 
@@ -44,8 +60,16 @@ pub struct Filters {
     pub invert: f32,
     pub grayscale: f32,
     pub opacity: f32,
-    pub blur: u32,
+    /// Standard deviation of the blur along `[x, y]`, in pixels.
+    pub blur: [f32; 2],
     pub blur_color: [f32; 4],
+    /// Blurs what's behind the texture, as in CSS `backdrop-filter: blur()`.
+    /// Only takes effect through a [`BackdropRenderer`](crate::backdrop::BackdropRenderer),
+    /// e.g. in a [`Scene`](crate::scene::Scene).
+    ///
+    /// The blur isn't tinted on its own. The texture is drawn over it as
+    /// usual, so a translucent texture is what tints the glass.
+    pub backdrop_blur: f32,
 }
 
 impl Default for Filters {
@@ -59,20 +83,65 @@ impl Default for Filters {
             sepia: 0.0,
             invert: 0.0,
             grayscale: 0.0,
-            blur: 0,
+            blur: [0., 0.],
             blur_color: [0., 0., 0., 0.],
+            backdrop_blur: 0.,
         }
     }
 }
 
+/// Drop shadow drawn behind a texture.
+///
+/// Like a CSS `box-shadow`, the shadow follows the rounded outline of the
+/// texture and is only visible outside of it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Shadow {
+    pub offset: [f32; 2],
+    /// Blur radius in pixels, the shadow fades out over roughly this distance.
+    pub softness: f32,
+    /// Grows (or, when negative, shrinks) the shadow before it's blurred.
+    pub spread: f32,
+    pub color: [f32; 4],
+}
+
+impl Shadow {
+    /// Returns how far the shadow reaches past each edge of the texture as
+    /// `[left, top, right, bottom]`. Must match `shadow_outsets` in the shader.
+    pub fn outsets(&self) -> [f32; 4] {
+        if self.color[3] <= 0.0 {
+            return [0.0; 4];
+        }
+
+        let reach = self.softness * 1.5 + self.spread;
+        [
+            (reach - self.offset[0]).max(0.0),
+            (reach - self.offset[1]).max(0.0),
+            (reach + self.offset[0]).max(0.0),
+            (reach + self.offset[1]).max(0.0),
+        ]
+    }
+}
+
+/// 2D transforms applied to a texture, like CSS `transform`.
+///
+/// The texture is scaled, then skewed, then rotated and finally translated,
+/// all around [`origin`](Self::origin). This matches
+/// `transform: translate() rotate() skew() scale()` in CSS.
 #[derive(Debug, Clone, Copy)]
 pub struct Transforms {
+    /// Clockwise rotation in degrees.
     pub rotate: f32,
     pub scale_x: f32,
     pub scale_y: f32,
+    /// Skew along the x axis in degrees.
     pub skew_x: f32,
+    /// Skew along the y axis in degrees.
     pub skew_y: f32,
+    /// Translation in pixels.
     pub translate: [f32; 2],
+    /// Point the texture is transformed around, as a fraction of its size.
+    /// Defaults to the center.
+    pub origin: [f32; 2],
 }
 
 impl Default for Transforms {
@@ -84,16 +153,47 @@ impl Default for Transforms {
             skew_x: 0.,
             skew_y: 0.,
             translate: [0., 0.],
+            origin: [0.5, 0.5],
         }
     }
 }
 
+impl Transforms {
+    /// Lowers the transforms to an affine matrix `[a, b, c, d, e, f]` for a
+    /// texture of `width` x `height`, in the same layout as CSS `matrix()`:
+    ///
+    /// ```text
+    /// x' = a * x + c * y + e
+    /// y' = b * x + d * y + f
+    /// ```
+    ///
+    /// Points are relative to the top left corner of the texture.
+    pub fn matrix(&self, width: f32, height: f32) -> [f32; 6] {
+        let (sin, cos) = self.rotate.to_radians().sin_cos();
+        let skew_x = self.skew_x.to_radians().tan();
+        let skew_y = self.skew_y.to_radians().tan();
+
+        // rotate * skew * scale
+        let a = (cos + -sin * skew_y) * self.scale_x;
+        let b = (sin + cos * skew_y) * self.scale_x;
+        let c = (cos * skew_x + -sin) * self.scale_y;
+        let d = (sin * skew_x + cos) * self.scale_y;
+
+        let origin = [self.origin[0] * width, self.origin[1] * height];
+        let e = origin[0] + self.translate[0] - (a * origin[0] + c * origin[1]);
+        let f = origin[1] + self.translate[1] - (b * origin[0] + d * origin[1]);
+
+        [a, b, c, d, e, f]
+    }
+}
+
 pub struct Buffer<'a> {
     width: f32,
     height: f32,
-    skew: [f32; 2],
     bytes: &'a [u8],
+    texture: Option<TextureId>,
     filters: Filters,
+    shadow: Shadow,
     scale: [f32; 2],
 }
 
@@ -102,9 +202,10 @@ impl<'a> Default for Buffer<'a> {
         Self {
             width: 0.0,
             height: 0.0,
-            skew: [0.0, 0.0],
             bytes: &[],
+            texture: None,
             filters: Filters::default(),
+            shadow: Shadow::default(),
             scale: [1.0, 1.0],
         }
     }
@@ -121,6 +222,14 @@ impl<'a> Buffer<'a> {
 
     pub fn set_bytes(&mut self, bytes: &'a [u8]) {
         self.bytes = bytes;
+        self.texture = None;
+    }
+
+    /// Draws an image previously uploaded with [`TextureRenderer::upload`]
+    /// instead of uploading bytes every frame.
+    pub fn set_texture(&mut self, texture: TextureId) {
+        self.texture = Some(texture);
+        self.bytes = &[];
     }
 
     pub fn set_size(&mut self, width_opt: Option<f32>, height_opt: Option<f32>) {
@@ -133,10 +242,6 @@ impl<'a> Buffer<'a> {
         }
     }
 
-    pub fn set_skew(&mut self, skew_x: f32, skew_y: f32) {
-        self.skew = [skew_x, skew_y];
-    }
-
     pub fn set_opacity(&mut self, val: f32) {
         self.filters.opacity = val;
     }
@@ -169,14 +274,33 @@ impl<'a> Buffer<'a> {
         self.filters.grayscale = val;
     }
 
-    pub fn set_blur(&mut self, val: u32) {
-        self.filters.blur = val;
+    pub fn set_blur(&mut self, val: f32) {
+        self.filters.blur = [val, val];
+    }
+
+    /// Blurs by a different amount along each axis, e.g. for motion blur.
+    pub fn set_blur_xy(&mut self, x: f32, y: f32) {
+        self.filters.blur = [x, y];
     }
 
     pub fn set_blur_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
         self.filters.blur_color = [r, g, b, a];
     }
 
+    pub fn set_backdrop_blur(&mut self, val: f32) {
+        self.filters.backdrop_blur = val;
+    }
+
+    pub fn set_shadow(&mut self, offset_x: f32, offset_y: f32, softness: f32, spread: f32) {
+        self.shadow.offset = [offset_x, offset_y];
+        self.shadow.softness = softness;
+        self.shadow.spread = spread;
+    }
+
+    pub fn set_shadow_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.shadow.color = [r, g, b, a];
+    }
+
     pub fn set_scale(&mut self, scale_x: f32, scale_y: f32) {
         self.scale = [scale_x, scale_y];
     }
@@ -185,7 +309,13 @@ impl<'a> Buffer<'a> {
 pub struct TextureRenderer {
     blur: blur::BlurRenderer,
     render_pipeline: wgpu::RenderPipeline,
+    depth_render_pipeline: wgpu::RenderPipeline,
+    /// Draws blurred textures before they're blurred, which ignores their
+    /// clips so that the blur can spread up to them.
+    unclipped_render_pipeline: wgpu::RenderPipeline,
+    clips: ClipBuffer,
     texture: wgpu::Texture,
+    cache: cache::TextureCache,
     bind_group: wgpu::BindGroup,
     vertex_buffer: buffers::VertexBuffer,
     index_buffer: buffers::IndexBuffer,
@@ -193,19 +323,31 @@ pub struct TextureRenderer {
     height: f32,
     max_texture_width: u32,
     max_texture_height: u32,
-    prepared_instances: usize,
+    // Indices of the prepared areas in the slice passed to `prepare`, one
+    // for each instance
+    prepared_areas: Vec<usize>,
+    runs: Vec<Run>,
+}
+
+/// Consecutive prepared instances that are either all blurred or all not.
+struct Run {
+    instances: Range<u32>,
+    /// Where the run is in the blur instances, if it's blurred.
+    blur_instances: Option<Range<u32>>,
 }
 
 pub struct TextureArea<'a> {
     pub left: f32,
     pub top: f32,
-    pub scale: f32,
-    pub rotation: f32,
+    pub transforms: Transforms,
     pub bounds: TextureBounds,
-    pub skew: [f32; 2],
     pub radius: [f32; 4],
     pub buffer: Buffer<'a>,
     pub depth: f32,
+    /// Clips of the [`ClipStack`] passed to
+    /// [`TextureRenderer::prepare_clips`] the texture, its shadow and its
+    /// blur are confined to.
+    pub clip: ClipId,
 }
 
 #[derive(Clone)]
@@ -228,6 +370,68 @@ impl TextureBounds {
 
 // Helper function for simple texture rendering (like moxnotify)
 impl<'a> TextureArea<'a> {
+    fn matrix(&self) -> [f32; 6] {
+        self.transforms
+            .matrix(self.buffer.width, self.buffer.height)
+    }
+
+    /// Region whose backdrop is blurred, or `None` without a backdrop blur.
+    ///
+    /// Rounded corners are approximated with circles, like the shadow does.
+    pub fn backdrop(&self) -> Option<Backdrop> {
+        let blur = self.buffer.filters.backdrop_blur;
+        if blur <= 0. {
+            return None;
+        }
+
+        let [sx, sy] = self.buffer.scale;
+        let [a, b, c, d, e, f] = self.matrix();
+        let (width, height) = (self.buffer.width, self.buffer.height);
+
+        // Percentages of the texture as `[bottom_left, bottom_right, top_left, top_right]`
+        let [bottom_left, bottom_right, top_left, top_right] =
+            self.radius.map(|r| (r * 0.01).min(0.5) * width.min(height));
+
+        Some(Backdrop {
+            rect: [self.left * sx, self.top * sy, width, height],
+            radius: [top_left, top_right, bottom_right, bottom_left],
+            transform: [a * sx, b * sy, c * sx, d * sy, e * sx, f * sy],
+            blur: blur * sx,
+            depth: self.depth,
+            clip: self.clip,
+        })
+    }
+
+    /// Returns the axis aligned `[left, top, width, height]` covered by the
+    /// texture and its shadow once transformed.
+    fn covered_rect(&self) -> [f32; 4] {
+        let [a, b, c, d, e, f] = self.matrix();
+        let [left, top, right, bottom] = self.buffer.shadow.outsets();
+        let (x0, y0) = (-left, -top);
+        let (x1, y1) = (self.buffer.width + right, self.buffer.height + bottom);
+
+        let (min, max) = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
+            .into_iter()
+            .map(|(x, y)| (a * x + c * y + e, b * x + d * y + f))
+            .fold(
+                ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]),
+                |(min, max), (x, y)| {
+                    (
+                        [min[0].min(x), min[1].min(y)],
+                        [max[0].max(x), max[1].max(y)],
+                    )
+                },
+            );
+
+        [
+            self.left + min[0],
+            self.top + min[1],
+            max[0] - min[0],
+            max[1] - min[1],
+        ]
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn simple(
         data: &'a [u8],
         left: f32,
@@ -245,13 +449,12 @@ impl<'a> TextureArea<'a> {
         Self {
             left,
             top,
-            scale: 1.0,
-            rotation: 0.0,
+            transforms: Transforms::default(),
             bounds,
-            skew: [0.0, 0.0],
             radius,
             buffer,
             depth,
+            clip: ClipId::default(),
         }
     }
 }
@@ -286,6 +489,9 @@ impl TextureRenderer {
         )
     }
 
+    /// Creates a renderer whose atlas has `max_textures` layers of
+    /// `texture_width` x `texture_height`. Images are packed into the layers,
+    /// so the layer size only bounds the size of a single image.
     pub fn with_texture_dimensions(
         device: &wgpu::Device,
         texture_format: wgpu::TextureFormat,
@@ -333,44 +539,66 @@ impl TextureRenderer {
                 label: Some("Viewport Bind Group Layout"),
             });
 
+        let clips = ClipBuffer::new(device);
+
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("texture_render_pipeline_layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &viewport_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &viewport_bind_group_layout,
+                    clips.bind_group_layout(),
+                ],
                 immediate_size: 0,
             });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shader.wgsl"));
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("texture_render_pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[buffers::Vertex::desc(), TextureInstance::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: texture_format,
-                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            cache: None,
-            multiview_mask: None,
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("texture_shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{}{}", include_str!("shader.wgsl"), ClipBuffer::SHADER).into(),
+            ),
         });
 
+        let create_pipeline = |label, depth_stencil, fragment_entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[buffers::Vertex::desc(), TextureInstance::desc()],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(fragment_entry_point),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: texture_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil,
+                multisample: wgpu::MultisampleState::default(),
+                cache: None,
+                multiview_mask: None,
+            })
+        };
+
+        let render_pipeline = create_pipeline("texture_render_pipeline", None, "fs_main");
+        let depth_render_pipeline = create_pipeline(
+            "texture_depth_render_pipeline",
+            Some(depth_stencil_state()),
+            "fs_main",
+        );
+        let unclipped_render_pipeline =
+            create_pipeline("texture_unclipped_render_pipeline", None, "fs_unclipped");
+
         let texture_size = wgpu::Extent3d {
             width: texture_width,
             height: texture_height,
@@ -444,12 +672,17 @@ impl TextureRenderer {
         let instance_buffer = buffers::instance::InstanceBuffer::new(device, &[]);
 
         Self {
-            prepared_instances: 0,
+            prepared_areas: Vec::new(),
             max_texture_width: texture_width,
             max_texture_height: texture_height,
             instance_buffer,
             render_pipeline,
+            depth_render_pipeline,
+            unclipped_render_pipeline,
+            clips,
+            runs: Vec::new(),
             texture,
+            cache: cache::TextureCache::new(texture_width, texture_height, max_textures),
             index_buffer,
             vertex_buffer,
             bind_group,
@@ -470,22 +703,113 @@ impl TextureRenderer {
             .resize(device, width as u32, height as u32, texture_format);
     }
 
+    /// Uploads the clips that [`TextureArea::clip`] refers to.
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.clips.prepare(device, queue, clips);
+        self.blur.prepare_clips(device, queue, clips);
+    }
+
+    /// Uploads an image once and returns a handle that later
+    /// [`TextureArea`]s can draw with [`Buffer::set_texture`].
+    ///
+    /// Images are packed into the layers of the texture array, so many small
+    /// images share one layer. When the atlas is full, the least recently
+    /// drawn images are evicted. Images drawn since the last
+    /// [`prepare`](Self::prepare) are never evicted, so this fails only when
+    /// they alone fill the atlas, or when `data` holds fewer than
+    /// `4 * width * height` bytes.
+    pub fn upload(
+        &mut self,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) -> anyhow::Result<TextureId> {
+        let Some((id, allocation)) = self.cache.insert(
+            width.min(self.max_texture_width),
+            height.min(self.max_texture_height),
+        ) else {
+            anyhow::bail!("Texture atlas is full with textures used by the current frame");
+        };
+
+        if let Err(err) = self.write_region(queue, &allocation, width, data) {
+            self.cache.remove(id);
+            return Err(err);
+        }
+
+        Ok(id)
+    }
+
+    /// Returns whether `texture` is still resident, i.e. hasn't been evicted
+    /// or removed.
+    pub fn contains(&self, texture: TextureId) -> bool {
+        self.cache.contains(texture)
+    }
+
+    /// Frees the atlas space used by `texture`. Returns `false` if it was already
+    /// evicted.
+    pub fn remove(&mut self, texture: TextureId) -> bool {
+        self.cache.remove(texture)
+    }
+
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         textures: &[TextureArea],
     ) {
-        self.prepared_instances = textures.len();
+        self.cache.begin_frame();
 
-        if textures.is_empty() {
-            self.blur.prepare(device, queue, textures);
-            return;
-        }
-
-        let mut instances = Vec::new();
+        let mut blurred = Vec::new();
+        let mut instances = Vec::with_capacity(textures.len());
+        self.prepared_areas.clear();
+        self.runs.clear();
 
         textures.iter().enumerate().for_each(|(i, texture)| {
+            // Areas whose handle got evicted, that don't fit into the atlas
+            // this frame or whose bytes are too short are skipped
+            let allocation = match texture.buffer.texture {
+                Some(id) => self.cache.get(id),
+                None => {
+                    let width = texture.buffer.width as u32;
+                    let height = texture.buffer.height as u32;
+
+                    self.cache
+                        .insert_transient(
+                            width.min(self.max_texture_width),
+                            height.min(self.max_texture_height),
+                        )
+                        .filter(|allocation| {
+                            self.write_region(queue, allocation, width, texture.buffer.bytes)
+                                .is_ok()
+                        })
+                }
+            };
+            let Some(allocation) = allocation else {
+                return;
+            };
+
+            let [a, b, c, d, e, f] = texture.matrix();
+
+            let index = instances.len() as u32;
+            let blur_index = blurred.len() as u32;
+            let is_blurred = texture.buffer.filters.blur.iter().any(|sigma| *sigma > 0.);
+            match self.runs.last_mut() {
+                Some(run) if run.blur_instances.is_some() == is_blurred => {
+                    run.instances.end += 1;
+                    if let Some(blur_instances) = &mut run.blur_instances {
+                        blur_instances.end += 1;
+                    }
+                }
+                _ => self.runs.push(Run {
+                    instances: index..index + 1,
+                    blur_instances: is_blurred.then_some(blur_index..blur_index + 1),
+                }),
+            }
+            if is_blurred {
+                blurred.push(texture);
+            }
+            self.prepared_areas.push(i);
             instances.push(TextureInstance {
                 filters1: [
                     texture.buffer.filters.opacity,
@@ -499,9 +823,10 @@ impl TextureRenderer {
                     texture.buffer.filters.invert,
                     texture.buffer.filters.grayscale,
                 ],
-                rotation_depth: [texture.rotation, texture.depth],
+                transform: [a, b, c, d],
+                translate: [e, f],
+                depth: texture.depth,
                 scale: texture.buffer.scale,
-                skew: texture.skew,
                 rect: [
                     texture.left,
                     texture.top,
@@ -510,92 +835,34 @@ impl TextureRenderer {
                 ],
                 radius: texture.radius,
                 texture_bounds: [
+                    allocation.x as f32 / self.max_texture_width as f32,
+                    allocation.y as f32 / self.max_texture_height as f32,
+                    (allocation.x + allocation.width) as f32 / self.max_texture_width as f32,
+                    (allocation.y + allocation.height) as f32 / self.max_texture_height as f32,
+                ],
+                shadow: [
+                    texture.buffer.shadow.offset[0],
+                    texture.buffer.shadow.offset[1],
+                    texture.buffer.shadow.softness,
+                    texture.buffer.shadow.spread,
+                ],
+                layer: allocation.layer,
+                clip_bounds: [
                     texture.bounds.left as f32,
                     texture.bounds.top as f32,
                     texture.bounds.right as f32,
                     texture.bounds.bottom as f32,
                 ],
-                shadow: [0., 0., 0.],
+                shadow_color: texture.buffer.shadow.color,
+                clip: texture.clip,
             });
-
-            // Calculate actual texture dimensions and bytes_per_row
-            let tex_width = (texture.buffer.width as u32).min(self.max_texture_width);
-            let tex_height = (texture.buffer.height as u32).min(self.max_texture_height);
-
-            // bytes_per_row must be aligned to 256 bytes for wgpu
-            let unpadded_bytes_per_row = 4 * tex_width;
-            let bytes_per_row = unpadded_bytes_per_row.div_ceil(256) * 256;
-
-            // Check if we need to pad the data
-            if bytes_per_row != unpadded_bytes_per_row {
-                // Need to pad each row to meet alignment requirement
-                let mut padded_data = Vec::with_capacity((bytes_per_row * tex_height) as usize);
-                for y in 0..tex_height {
-                    let row_start = (y * unpadded_bytes_per_row) as usize;
-                    let row_end = row_start + unpadded_bytes_per_row as usize;
-
-                    // Copy the actual row data
-                    if row_end <= texture.buffer.bytes.len() {
-                        padded_data.extend_from_slice(&texture.buffer.bytes[row_start..row_end]);
-                        // Add padding to reach bytes_per_row alignment
-                        padded_data.resize(
-                            padded_data.len() + (bytes_per_row - unpadded_bytes_per_row) as usize,
-                            0,
-                        );
-                    }
-                }
-
-                queue.write_texture(
-                    wgpu::TexelCopyTextureInfo {
-                        texture: &self.texture,
-                        mip_level: 0,
-                        origin: wgpu::Origin3d {
-                            x: 0,
-                            y: 0,
-                            z: i as u32,
-                        },
-                        aspect: wgpu::TextureAspect::All,
-                    },
-                    &padded_data,
-                    wgpu::TexelCopyBufferLayout {
-                        offset: 0,
-                        bytes_per_row: Some(bytes_per_row),
-                        rows_per_image: None,
-                    },
-                    wgpu::Extent3d {
-                        width: tex_width,
-                        height: tex_height,
-                        depth_or_array_layers: 1,
-                    },
-                );
-            } else {
-                // No padding needed, use data as-is
-                queue.write_texture(
-                    wgpu::TexelCopyTextureInfo {
-                        texture: &self.texture,
-                        mip_level: 0,
-                        origin: wgpu::Origin3d {
-                            x: 0,
-                            y: 0,
-                            z: i as u32,
-                        },
-                        aspect: wgpu::TextureAspect::All,
-                    },
-                    texture.buffer.bytes,
-                    wgpu::TexelCopyBufferLayout {
-                        offset: 0,
-                        bytes_per_row: Some(bytes_per_row),
-                        rows_per_image: None,
-                    },
-                    wgpu::Extent3d {
-                        width: tex_width,
-                        height: tex_height,
-                        depth_or_array_layers: 1,
-                    },
-                );
-            }
         });
 
+        if instances.is_empty() {
+            self.blur.prepare(device, queue, &blurred);
+            return;
+        }
+
         let instance_buffer_size = std::mem::size_of::<TextureInstance>() * instances.len();
 
         if self.instance_buffer.size() < instance_buffer_size as u32 {
@@ -605,53 +872,260 @@ impl TextureRenderer {
 
         self.instance_buffer.write(queue, &instances);
 
-        self.blur.prepare(device, queue, textures);
+        self.blur.prepare(device, queue, &blurred);
     }
 
+    /// Writes an image with rows of `width` pixels into `allocation`,
+    /// cropping whatever doesn't fit. Fails if `bytes` is too short to
+    /// cover the region.
+    fn write_region(
+        &self,
+        queue: &wgpu::Queue,
+        allocation: &atlas::Allocation,
+        width: u32,
+        bytes: &[u8],
+    ) -> anyhow::Result<()> {
+        let tex_width = allocation.width;
+        let tex_height = allocation.height;
+
+        let needed = 4 * width as usize * tex_height as usize;
+        if width < tex_width || bytes.len() < needed {
+            anyhow::bail!(
+                "Texture data is {} bytes long, but {needed} are needed",
+                bytes.len()
+            );
+        }
+
+        // bytes_per_row must be aligned to 256 bytes for wgpu
+        let unpadded_bytes_per_row = 4 * tex_width;
+        let bytes_per_row = unpadded_bytes_per_row.div_ceil(256) * 256;
+        let src_bytes_per_row = 4 * width;
+
+        // Check if we need to pad the data
+        let data = if bytes_per_row != unpadded_bytes_per_row || tex_width != width {
+            // Need to pad each row to meet alignment requirement
+            let mut padded_data = Vec::with_capacity((bytes_per_row * tex_height) as usize);
+            for y in 0..tex_height {
+                let row_start = (y * src_bytes_per_row) as usize;
+                let row_end = row_start + unpadded_bytes_per_row as usize;
+
+                // Copy the actual row data
+                if row_end <= bytes.len() {
+                    padded_data.extend_from_slice(&bytes[row_start..row_end]);
+                    // Add padding to reach bytes_per_row alignment
+                    padded_data.resize(
+                        padded_data.len() + (bytes_per_row - unpadded_bytes_per_row) as usize,
+                        0,
+                    );
+                }
+            }
+
+            std::borrow::Cow::Owned(padded_data)
+        } else {
+            // No padding needed, use data as-is
+            std::borrow::Cow::Borrowed(bytes)
+        };
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: allocation.x,
+                    y: allocation.y,
+                    z: allocation.layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: tex_width,
+                height: tex_height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Draws the prepared textures over `texture_view` in the order they
+    /// were prepared in.
     pub fn render(
         &self,
         texture_view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
         viewport: &crate::viewport::Viewport,
     ) {
-        if self.prepared_instances > 0 {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("standard_render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &self.blur.intermediate_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                    depth_slice: None,
-                })],
-                ..Default::default()
-            });
+        self.render_instances(
+            texture_view,
+            None,
+            encoder,
+            viewport,
+            0..self.prepared_areas.len() as u32,
+        );
+    }
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.bind_group, &[]);
-            render_pass.set_bind_group(1, &viewport.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(
-                0..self.index_buffer.size(),
-                0,
-                0..self.prepared_instances as u32,
-            );
+    /// Like [`render`](Self::render), but textures are tested against and
+    /// written to `depth_view`, like shapes and text. This lets them
+    /// interleave with those by [`TextureArea::depth`].
+    pub fn render_with_depth(
+        &self,
+        texture_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        viewport: &crate::viewport::Viewport,
+    ) {
+        self.render_instances(
+            texture_view,
+            Some(depth_view),
+            encoder,
+            viewport,
+            0..self.prepared_areas.len() as u32,
+        );
+    }
 
-            drop(render_pass);
-        }
+    /// Draws only the areas in `areas`, given as indices into the slice
+    /// passed to [`prepare`](Self::prepare).
+    #[cfg(all(feature = "shape_renderer", feature = "text_renderer"))]
+    pub(crate) fn render_range(
+        &self,
+        texture_view: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
+        encoder: &mut wgpu::CommandEncoder,
+        viewport: &crate::viewport::Viewport,
+        areas: Range<usize>,
+    ) {
+        let start = self.prepared_areas.partition_point(|i| *i < areas.start);
+        let end = self.prepared_areas.partition_point(|i| *i < areas.end);
 
-        self.blur.render(
+        self.render_instances(
             texture_view,
+            depth_view,
             encoder,
             viewport,
-            &self.vertex_buffer,
-            &self.index_buffer,
+            start as u32..end as u32,
         );
     }
+
+    fn render_instances(
+        &self,
+        texture_view: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
+        encoder: &mut wgpu::CommandEncoder,
+        viewport: &crate::viewport::Viewport,
+        instances: Range<u32>,
+    ) {
+        self.runs.iter().for_each(|run| {
+            let start = run.instances.start.max(instances.start);
+            let end = run.instances.end.min(instances.end);
+            if start >= end {
+                return;
+            }
+
+            match &run.blur_instances {
+                None => self.draw(
+                    match depth_view {
+                        Some(_) => &self.depth_render_pipeline,
+                        None => &self.render_pipeline,
+                    },
+                    texture_view,
+                    depth_view,
+                    wgpu::LoadOp::Load,
+                    encoder,
+                    viewport,
+                    start..end,
+                ),
+                Some(blur_instances) => {
+                    // Blurred textures are drawn on their own first, so that
+                    // the blur doesn't pick up anything else
+                    self.draw(
+                        &self.unclipped_render_pipeline,
+                        self.blur.intermediate_view(),
+                        None,
+                        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        encoder,
+                        viewport,
+                        start..end,
+                    );
+
+                    // Same range, counted in the blur instances
+                    let blur_start = blur_instances.start + (start - run.instances.start);
+                    let blur_end = blur_instances.start + (end - run.instances.start);
+                    self.blur.render(
+                        texture_view,
+                        depth_view,
+                        encoder,
+                        viewport,
+                        &self.vertex_buffer,
+                        &self.index_buffer,
+                        blur_start..blur_end,
+                    );
+                }
+            }
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        pipeline: &wgpu::RenderPipeline,
+        texture_view: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
+        load: wgpu::LoadOp<wgpu::Color>,
+        encoder: &mut wgpu::CommandEncoder,
+        viewport: &crate::viewport::Viewport,
+        instances: Range<u32>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("standard_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: depth_view.map(|view| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+            ..Default::default()
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, &viewport.bind_group, &[]);
+        render_pass.set_bind_group(2, self.clips.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.index_buffer.size(), 0, instances);
+    }
+}
+
+/// Depth state of the pipelines that draw into a shared depth buffer, the
+/// same as the shape and text renderers use.
+fn depth_stencil_state() -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: wgpu::TextureFormat::Depth32Float,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
 }
 
 pub fn create_depth_buffer(
@@ -14,105 +14,80 @@ struct VertexInput {
 struct InstanceInput {
     @location(1) filters1: vec4<f32>,  // [opacity, brightness, contrast, saturation]
     @location(2) filters2: vec4<f32>,  // [hue_rotate, sepia, invert, grayscale]
-    @location(3) rotation_depth: vec2<f32>,  // [rotation, depth]
-    @location(4) scale: vec2<f32>,
-    @location(5) skew: vec2<f32>,
-    @location(6) rect: vec4<f32>,
-    @location(7) radius: vec4<f32>,
-    @location(8) texture_bounds: vec4<f32>,
-    @location(9) shadow: vec3<f32>,
+    @location(3) transform: vec4<f32>,  // linear part of the affine transform, column major
+    @location(4) translate: vec2<f32>,
+    @location(5) depth: f32,
+    @location(6) scale: vec2<f32>,
+    @location(7) rect: vec4<f32>,
+    @location(8) radius: vec4<f32>,
+    @location(9) texture_bounds: vec4<f32>,  // UV rect of the image inside its layer
+    @location(10) shadow: vec4<f32>,  // [offset_x, offset_y, softness, spread]
+    @location(11) layer: u32,
+    @location(12) clip_bounds: vec4<f32>,
+    @location(13) shadow_color: vec4<f32>,
+    @location(14) clip: vec2<u32>,
 };
 
 struct VertexOutput {
     @location(0) layer: u32,
-    @location(1) opacity: f32,
-    @location(2) rotation: f32,
-    @location(3) brightness: f32,
-    @location(4) contrast: f32,
-    @location(5) saturation: f32,
-    @location(6) hue_rotate: f32,
-    @location(7) sepia: f32,
-    @location(8) invert: f32,
-    @location(9) grayscale: f32,
-    @location(10) tex_coords: vec2<f32>,
-    @location(11) size: vec2<f32>,
-    @location(12) surface_position: vec2<f32>,
-    @location(13) shadow_softness: f32,
-    @location(14) shadow_offset: vec2<f32>,
-    @location(15) radius: vec4<f32>,
-    @location(16) texture_bounds: vec4<f32>,
+    @location(1) filters1: vec4<f32>,  // [opacity, brightness, contrast, saturation]
+    @location(2) filters2: vec4<f32>,  // [hue_rotate, sepia, invert, grayscale]
+    @location(3) tex_coords: vec2<f32>,
+    @location(4) size: vec2<f32>,
+    @location(5) surface_position: vec2<f32>,
+    @location(6) shadow: vec4<f32>,  // [offset_x, offset_y, softness, spread]
+    @location(7) radius: vec4<f32>,
+    @location(8) texture_bounds: vec4<f32>,
+    @location(9) clip_bounds: vec4<f32>,
+    @location(10) shadow_color: vec4<f32>,
+    @location(11) @interpolate(flat) clip: vec2<u32>,  // [first, count] of the clip rects
     @builtin(position) clip_position: vec4<f32>,
 };
 
-fn rotation_matrix(angle: f32) -> mat2x2<f32> {
-    let angle_inner = angle * pi / 180.0;
-    let sinTheta = sin(angle_inner);
-    let cosTheta = cos(angle_inner);
-    return mat2x2<f32>(
-        cosTheta, -sinTheta,
-        sinTheta, cosTheta
-    );
-}
+// Must match `Shadow::outsets`
+fn shadow_outsets(shadow: vec4<f32>, color: vec4<f32>) -> vec4<f32> {
+    if color.a <= 0.0 {
+        return vec4<f32>(0.0);
+    }
 
-fn skew_matrix(skew_x: f32, skew_y: f32) -> mat2x2<f32> {
-    return mat2x2<f32>(
-        vec2<f32>(1.0, skew_y * pi / 180.0),
-        vec2<f32>(skew_x * pi / 180.0, 1.0)
-    );
+    let reach = shadow.z * 1.5 + shadow.w;
+    return max(vec4<f32>(reach - shadow.xy, reach + shadow.xy), vec4<f32>(0.0));
 }
 
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
-    @builtin(instance_index) instance_idx: u32,
 ) -> VertexOutput {
     var out: VertexOutput;
 
-    // Unpack data
-    let opacity = instance.filters1.x;
-    let brightness = instance.filters1.y;
-    let contrast = instance.filters1.z;
-    let saturation = instance.filters1.w;
-    
-    let hue_rotate = instance.filters2.x;
-    let sepia = instance.filters2.y;
-    let invert = instance.filters2.z;
-    let grayscale = instance.filters2.w;
-    
-    let rotation = instance.rotation_depth.x;
-    let depth = instance.rotation_depth.y;
-
-    let pos = instance.rect.xy * instance.scale;
     let size = instance.rect.zw * instance.scale;
 
-    let local_pos = (model.position - vec2<f32>(0.5)) * size;
-    let rotated_pos = rotation_matrix(rotation) * local_pos;
-    let position = rotated_pos + pos + size * 0.5;
+    // Grow the quad so that the shadow can be drawn outside the texture
+    let outsets = shadow_outsets(instance.shadow, instance.shadow_color);
+    let corner = mix(-outsets.xy, instance.rect.zw + outsets.zw, model.position);
+
+    let transform = mat2x2<f32>(instance.transform.xy, instance.transform.zw);
+    let position = (instance.rect.xy + transform * corner + instance.translate) * instance.scale;
 
     // Convert from pixel coordinates to NDC (like shape_renderer)
     let resolution = vec2<f32>(params.screen_resolution);
     let ndc = (position / resolution) * 2.0 - vec2<f32>(1.0, 1.0);
     let ndc_fixed = vec2<f32>(ndc.x, -ndc.y);
 
-    out.clip_position = vec4<f32>(ndc_fixed, depth, 1.0);
-    out.tex_coords = model.position;
-    out.layer = instance_idx;
+    out.clip_position = vec4<f32>(ndc_fixed, instance.depth, 1.0);
+    out.tex_coords = corner / instance.rect.zw;
+    out.layer = instance.layer;
     out.size = size;
     out.texture_bounds = instance.texture_bounds;
+    out.clip_bounds = instance.clip_bounds;
     out.surface_position = position;
-    out.opacity = opacity;
-    out.rotation = rotation;
     out.radius = instance.radius;
-    out.brightness = brightness;
-    out.contrast = contrast;
-    out.saturation = saturation;
-    out.hue_rotate = hue_rotate;
-    out.sepia = sepia;
-    out.invert = invert;
-    out.grayscale = grayscale;
-    out.shadow_offset = instance.shadow.xy;
-    out.shadow_softness = instance.shadow.z;
+    out.filters1 = instance.filters1;
+    out.filters2 = instance.filters2;
+    out.shadow = vec4<f32>(instance.shadow.xy * instance.scale, instance.shadow.zw * instance.scale.x);
+    out.shadow_color = instance.shadow_color;
+    out.clip = instance.clip;
 
     return out;
 }
@@ -192,36 +167,66 @@ var t_diffuse: texture_2d_array<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// Abramowitz and Stegun approximation, max error 5e-4
+fn erf(x: f32) -> f32 {
+    let a = abs(x);
+    var r = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+    r = r * r;
+    return sign(x) * (1.0 - 1.0 / (r * r));
+}
+
+// Coverage of a shape blurred with a gaussian, `dist` being the signed
+// distance to the unblurred shape. The blur radius spans two sigmas.
 fn gaussian_shadow(dist: f32, blur_radius: f32) -> f32 {
     if blur_radius <= 0.0 {
         return select(0.0, 1.0, dist <= 0.0);
     }
-    
-    let normalized_dist = abs(dist) / blur_radius;
-    let gaussian = exp(-normalized_dist * normalized_dist * 0.5);
-    let falloff = smoothstep(0.0, 1.0, 1.0 - normalized_dist);
 
-    return gaussian * falloff;
+    let sigma = blur_radius * 0.5;
+    return 0.5 - 0.5 * erf(dist / (sigma * sqrt(2.0)));
 }
 
-fn is_outside_container(surface_pos: vec2<f32>, texture_bounds: vec4<f32>) -> bool {
-    let container_left = texture_bounds.x;
-    let container_top = texture_bounds.y;
-    let container_right = texture_bounds.z;
-    let container_bottom = texture_bounds.w;
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    var result = vec3<f32>(0.0);
+    for (var i = 0; i < 3; i = i + 1) {
+        if c[i] <= 0.04045 {
+            result[i] = c[i] / 12.92;
+        } else {
+            result[i] = pow((c[i] + 0.055) / 1.055, 2.4);
+        }
+    }
+    return result;
+}
+
+fn is_outside_container(surface_pos: vec2<f32>, clip_bounds: vec4<f32>) -> bool {
+    let container_left = clip_bounds.x;
+    let container_top = clip_bounds.y;
+    let container_right = clip_bounds.z;
+    let container_bottom = clip_bounds.w;
 
     return surface_pos.x < container_left || surface_pos.x > container_right || surface_pos.y < container_top || surface_pos.y > container_bottom;
 }
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+// Premultiplied color of the texture and its shadow
+fn shade(in: VertexOutput) -> vec4<f32> {
     // Clip to container bounds
-    if is_outside_container(in.surface_position, in.texture_bounds) {
+    if is_outside_container(in.surface_position, in.clip_bounds) {
         discard;
     }
 
-    // Sample texture (straight alpha)
-    let base_color = textureSample(t_diffuse, s_diffuse, in.tex_coords, in.layer);
+    let opacity = in.filters1.x;
+    let brightness = in.filters1.y;
+    let contrast = in.filters1.z;
+    let saturation = in.filters1.w;
+
+    let hue_rotate_angle = in.filters2.x;
+    let sepia_amount = in.filters2.y;
+    let invert = in.filters2.z;
+    let grayscale_amount = in.filters2.w;
+
+    // Sample texture (straight alpha) from the image's region of the atlas
+    let atlas_coords = mix(in.texture_bounds.xy, in.texture_bounds.zw, clamp(in.tex_coords, vec2<f32>(0.0), vec2<f32>(1.0)));
+    let base_color = textureSample(t_diffuse, s_diffuse, atlas_coords, in.layer);
   
     // === ROUNDED CORNERS ===
     let centered_tex_coords = in.tex_coords - 0.5;
@@ -234,33 +239,64 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let texture_alpha = smoothstep(-texture_aa, texture_aa, -texture_dist);
 
     // === SHADOW ===
-    let shadow_offset_normalized = in.shadow_offset / in.size;
-    let shadow_coords = centered_tex_coords - shadow_offset_normalized;
-    let shadow_dist = sdf_rounded_rect(shadow_coords, half_extent + (in.shadow_offset / in.size) / 2., effective_radius);
-    let shadow_alpha = gaussian_shadow(shadow_dist, in.shadow_softness / min(in.size.x, in.size.y));
+    // Evaluated in pixels, so that spread and softness are uniform on both axes
+    let shadow_offset = in.shadow.xy;
+    let shadow_softness = in.shadow.z;
+    let shadow_spread = in.shadow.w;
+    let shadow_coords = centered_tex_coords * in.size - shadow_offset;
+    let shadow_radius = max(effective_radius * min(in.size.x, in.size.y) + shadow_spread, vec4<f32>(0.0));
+    let shadow_dist = sdf_rounded_rect(shadow_coords, max(in.size * 0.5 + shadow_spread, vec2<f32>(0.0)), shadow_radius);
+    let shadow_alpha = gaussian_shadow(shadow_dist, shadow_softness);
 
     // === COLOR FILTERS ===
     var final_rgb = base_color.rgb;
     
     // Check if we need to apply color filters
-    let needs_filters = in.brightness != 0.0 || in.contrast != 1.0 || in.saturation != 1.0 || 
-                       in.hue_rotate != 0.0 || in.sepia != 0.0 || in.grayscale != 0.0 || in.invert != 0.0;
+    let needs_filters = brightness != 0.0 || contrast != 1.0 || saturation != 1.0 || 
+                       hue_rotate_angle != 0.0 || sepia_amount != 0.0 || grayscale_amount != 0.0 || invert != 0.0;
     
     if needs_filters {
         // Apply color matrix filters
         var filtered_color = vec4<f32>(final_rgb, 1.0);
-        filtered_color = brightness_matrix(in.brightness) * contrast_matrix(in.contrast) * saturation_matrix(in.saturation) * filtered_color;
+        filtered_color = brightness_matrix(brightness) * contrast_matrix(contrast) * saturation_matrix(saturation) * filtered_color;
         
         // Apply other color transforms  
-        let hue_rotated = hue_rotate(filtered_color.rgb, in.hue_rotate);
-        let sepia_applied = sepia(hue_rotated, in.sepia);
-        let gray_applied = grayscale(sepia_applied, in.grayscale);
-        final_rgb = mix(gray_applied, vec3<f32>(1.0) - gray_applied, in.invert);
+        let hue_rotated = hue_rotate(filtered_color.rgb, hue_rotate_angle);
+        let sepia_applied = sepia(hue_rotated, sepia_amount);
+        let gray_applied = grayscale(sepia_applied, grayscale_amount);
+        final_rgb = mix(gray_applied, vec3<f32>(1.0) - gray_applied, invert);
     }
 
     // Apply opacity and rounded corners to alpha
-    let final_alpha = base_color.a * texture_alpha * in.opacity;
-    
-    // Premultiply and return (shadow disabled for now to maintain transparency)
-    return vec4<f32>(final_rgb * final_alpha, final_alpha);
+    let final_alpha = base_color.a * texture_alpha * opacity;
+
+    // Like a box-shadow, the shadow is only visible outside of the texture
+    let shadow_coverage = in.shadow_color.a * shadow_alpha * (1.0 - texture_alpha) * opacity;
+    let shadow = vec4<f32>(srgb_to_linear(in.shadow_color.rgb) * shadow_coverage, shadow_coverage);
+
+    return vec4<f32>(final_rgb * final_alpha, final_alpha) + shadow;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(in) * clip_coverage(in.clip_position.xy, in.clip);
+
+    // Keep empty pixels out of the depth buffer
+    if color.a < 0.001 {
+        discard;
+    }
+
+    // Premultiplied
+    return color;
+}
+
+// Draws blurred textures for their blur, which is clipped instead
+@fragment
+fn fs_unclipped(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(in);
+    if color.a < 0.001 {
+        discard;
+    }
+
+    return color;
 }
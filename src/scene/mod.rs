@@ -0,0 +1,368 @@
+use crate::backdrop::BackdropRenderer;
+use crate::clip::ClipStack;
+use crate::shape_renderer::{Shape, ShapeRenderer};
+use crate::text_renderer::{TextArea, TextRenderer};
+use crate::texture_renderer::{self, TextureArea, TextureRenderer};
+use crate::viewport::{Resolution, Viewport};
+use std::ops::Range;
+
+/// Anything a [`Scene`] can draw.
+pub enum Primitive<'a> {
+    Shape(Shape),
+    Texture(TextureArea<'a>),
+    Text(TextArea<'a>),
+}
+
+impl Primitive<'_> {
+    pub fn depth(&self) -> f32 {
+        match self {
+            Self::Shape(shape) => shape.instance.depth,
+            Self::Texture(texture) => texture.depth,
+            Self::Text(text) => text.depth,
+        }
+    }
+}
+
+impl From<Shape> for Primitive<'_> {
+    fn from(shape: Shape) -> Self {
+        Self::Shape(shape)
+    }
+}
+
+impl<'a> From<TextureArea<'a>> for Primitive<'a> {
+    fn from(texture: TextureArea<'a>) -> Self {
+        Self::Texture(texture)
+    }
+}
+
+impl<'a> From<TextArea<'a>> for Primitive<'a> {
+    fn from(text: TextArea<'a>) -> Self {
+        Self::Text(text)
+    }
+}
+
+/// Consecutive primitives of one kind, drawn with a single call.
+enum Batch {
+    /// Range of the prepared shapes.
+    Shapes(Range<u32>),
+    /// Range of the areas passed to the texture renderer.
+    Textures(Range<usize>),
+    /// Depth all text of the batch was prepared with.
+    Text(f32),
+    /// Index of the backdrop blurred for the primitive after it.
+    Backdrop(u32),
+}
+
+/// Draws shapes, textures and text layered by their depth.
+///
+/// The scene owns the renderers, the [`Viewport`] and the depth buffer, and
+/// opens all render passes itself. Primitives are drawn back to front:
+/// larger depths first, and primitives with the same depth in the order
+/// they were given in.
+///
+/// Shapes and textures with a backdrop blur blur everything drawn before
+/// them, which requires a target created with
+/// [`wgpu::TextureUsages::COPY_SRC`].
+///
+/// Shapes, textures and text are confined to the clips they refer to once
+/// those are uploaded with [`prepare_clips`](Self::prepare_clips). Text is
+/// also clipped by the bounds of its area, e.g. from
+/// [`ClipStack::text_bounds`].
+///
+/// # Example
+///
+/// ```ignore
+/// use moxui::scene::{Primitive, Scene};
+///
+/// let mut scene = Scene::new(&device, &queue, format, width, height);
+///
+/// scene.prepare(&device, &queue, &mut font_system, vec![
+///     Primitive::Shape(card.into()),
+///     Primitive::Texture(icon),
+///     Primitive::Text(label),
+/// ])?;
+/// scene.render(&mut encoder, &surface_view)?;
+/// ```
+pub struct Scene {
+    format: wgpu::TextureFormat,
+    viewport: Viewport,
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    clear_color: Option<wgpu::Color>,
+    shapes: ShapeRenderer,
+    textures: TextureRenderer,
+    backdrop: BackdropRenderer,
+    text: TextRenderer,
+    batches: Vec<Batch>,
+}
+
+impl Scene {
+    /// Creates a scene whose texture atlas has two 1024x1024 layers. Use
+    /// [`with_texture_renderer`](Self::with_texture_renderer) for a
+    /// differently sized atlas.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let textures = TextureRenderer::with_layers(device, format, 1024, width, height, 2);
+
+        Self::with_texture_renderer(device, queue, format, width, height, textures)
+    }
+
+    /// Creates a scene that draws textures with `textures`, which has to
+    /// use the same `format`.
+    pub fn with_texture_renderer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        textures: TextureRenderer,
+    ) -> Self {
+        let mut viewport = Viewport::new(device);
+        viewport.update(queue, Resolution { width, height });
+
+        let (depth_texture, depth_view) =
+            texture_renderer::create_depth_buffer(device, width.max(1), height.max(1));
+
+        Self {
+            format,
+            viewport,
+            _depth_texture: depth_texture,
+            depth_view,
+            clear_color: Some(wgpu::Color::TRANSPARENT),
+            shapes: ShapeRenderer::new(device, format),
+            textures,
+            backdrop: BackdropRenderer::new(device, format, width, height),
+            text: TextRenderer::new(device, queue, format),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Resizes the viewport and the depth buffer. Call this whenever the
+    /// render target changes size.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        if self.viewport.resolution() == (Resolution { width, height }) {
+            return;
+        }
+
+        self.viewport.update(queue, Resolution { width, height });
+
+        let (depth_texture, depth_view) =
+            texture_renderer::create_depth_buffer(device, width.max(1), height.max(1));
+        self._depth_texture = depth_texture;
+        self.depth_view = depth_view;
+
+        self.textures
+            .resize(device, self.format, width as f32, height as f32);
+        self.backdrop.resize(device, width, height);
+    }
+
+    /// Color the target is cleared with before drawing, or `None` to draw
+    /// over its current contents. Defaults to transparent.
+    pub fn set_clear_color(&mut self, color: Option<wgpu::Color>) {
+        self.clear_color = color;
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    /// The texture renderer, e.g. to [`upload`](TextureRenderer::upload)
+    /// images once and draw them by handle.
+    pub fn texture_renderer(&mut self) -> &mut TextureRenderer {
+        &mut self.textures
+    }
+
+    /// Uploads the clips that the primitives refer to.
+    pub fn prepare_clips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, clips: &ClipStack) {
+        self.shapes.prepare_clips(device, queue, clips);
+        self.textures.prepare_clips(device, queue, clips);
+        self.backdrop.prepare_clips(device, queue, clips);
+        self.text.prepare_clips(device, queue, clips);
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        font_system: &mut glyphon::FontSystem,
+        mut primitives: Vec<Primitive>,
+    ) -> anyhow::Result<()> {
+        // Stable, so primitives with the same depth keep their order
+        primitives.sort_by(|a, b| b.depth().total_cmp(&a.depth()));
+
+        // Batches are drawn in order, so the depth test must never reject a
+        // later primitive. Give every primitive a depth closer than all
+        // primitives before it.
+        let count = primitives.len() as f32 + 1.;
+        let depth = |i: usize| 1. - (i as f32 + 1.) / count;
+
+        let mut shapes = Vec::new();
+        let mut textures = Vec::new();
+        let mut backdrops = Vec::new();
+        let mut text = Vec::new();
+        // Number of areas in each text batch
+        let mut text_batches = Vec::new();
+        self.batches.clear();
+
+        primitives
+            .into_iter()
+            .enumerate()
+            .for_each(|(i, primitive)| match primitive {
+                Primitive::Shape(mut shape) => {
+                    shape.instance.depth = depth(i);
+                    if let Some(backdrop) = shape.backdrop() {
+                        self.batches.push(Batch::Backdrop(backdrops.len() as u32));
+                        backdrops.push(backdrop);
+                    }
+                    shapes.push(shape);
+
+                    let end = shapes.len() as u32;
+                    match self.batches.last_mut() {
+                        Some(Batch::Shapes(range)) => range.end = end,
+                        _ => self.batches.push(Batch::Shapes(end - 1..end)),
+                    }
+                }
+                Primitive::Texture(mut texture) => {
+                    texture.depth = depth(i);
+                    if let Some(backdrop) = texture.backdrop() {
+                        self.batches.push(Batch::Backdrop(backdrops.len() as u32));
+                        backdrops.push(backdrop);
+                    }
+                    textures.push(texture);
+
+                    let end = textures.len();
+                    match self.batches.last_mut() {
+                        Some(Batch::Textures(range)) => range.end = end,
+                        _ => self.batches.push(Batch::Textures(end - 1..end)),
+                    }
+                }
+                Primitive::Text(area) => {
+                    text.push(area);
+                    match self.batches.last_mut() {
+                        Some(Batch::Text(batch_depth)) => {
+                            *batch_depth = depth(i);
+                            *text_batches.last_mut().unwrap() += 1;
+                        }
+                        _ => {
+                            self.batches.push(Batch::Text(depth(i)));
+                            text_batches.push(1);
+                        }
+                    }
+                }
+            });
+
+        self.shapes.prepare_shapes(device, queue, &shapes);
+        self.textures.prepare(device, queue, &textures);
+        self.backdrop.prepare(device, queue, &backdrops);
+
+        // Text of a batch shares the depth of its last area, so that each
+        // batch is drawn by its own glyphon renderer
+        let depths = self.batches.iter().filter_map(|batch| match batch {
+            Batch::Text(depth) => Some(*depth),
+            _ => None,
+        });
+        let mut areas = text.iter_mut();
+        depths.zip(text_batches).for_each(|(depth, count)| {
+            areas
+                .by_ref()
+                .take(count)
+                .for_each(|area| area.depth = depth);
+        });
+
+        self.text
+            .prepare(device, queue, &self.viewport, &text, font_system)
+    }
+
+    /// Draws the prepared primitives into `target`, which can be repeated,
+    /// e.g. for several targets of the same size.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+    ) -> anyhow::Result<()> {
+        // Text effects are drawn from the text on its own, outside of the
+        // passes the text is drawn in
+        self.text.render_effects(encoder)?;
+
+        let mut batches = self.batches.iter().peekable();
+        let mut first = true;
+
+        loop {
+            // Shapes and text draw into passes of their own, and the first
+            // pass also clears the target and the depth buffer
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("scene_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: match self.clear_color {
+                            Some(color) if first => wgpu::LoadOp::Clear(color),
+                            _ => wgpu::LoadOp::Load,
+                        },
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: if first {
+                            wgpu::LoadOp::Clear(1.0)
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            first = false;
+
+            while let Some(batch) =
+                batches.next_if(|batch| matches!(batch, Batch::Shapes(_) | Batch::Text(_)))
+            {
+                match batch {
+                    Batch::Shapes(range) => {
+                        self.shapes
+                            .render_range(&mut render_pass, &self.viewport, range.clone())
+                    }
+                    Batch::Text(depth) => {
+                        self.text.render_range(&mut render_pass, *depth..=*depth)?
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            drop(render_pass);
+
+            // The texture and backdrop renderers open their own passes
+            match batches.next() {
+                Some(Batch::Textures(range)) => self.textures.render_range(
+                    target,
+                    Some(&self.depth_view),
+                    encoder,
+                    &self.viewport,
+                    range.clone(),
+                ),
+                Some(Batch::Backdrop(i)) => self.backdrop.render_range(
+                    target,
+                    Some(&self.depth_view),
+                    encoder,
+                    &self.viewport,
+                    *i..*i + 1,
+                ),
+                Some(_) => unreachable!(),
+                None => return Ok(()),
+            }
+        }
+    }
+}
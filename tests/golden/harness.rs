@@ -0,0 +1,167 @@
+use moxui::image::Image;
+use moxui::offscreen::{Offscreen, Renderers};
+use moxui::viewport::Viewport;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Set to regenerate the goldens from the current output instead of
+/// comparing against them.
+const UPDATE_ENV: &str = "MOXUI_UPDATE_GOLDENS";
+
+/// Largest difference per channel that still counts as a match. Software
+/// rasterizers differ slightly in rounding and in their transcendentals.
+const TOLERANCE: u8 = 3;
+
+pub struct Gpu {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+/// Returns a device on a software adapter such as llvmpipe, lavapipe or
+/// WARP, so that goldens don't depend on the GPU of the machine.
+///
+/// Returns `None` when there's no software adapter, in which case the
+/// golden tests are skipped.
+pub fn gpu() -> Option<&'static Gpu> {
+    static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+
+    GPU.get_or_init(|| {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let adapter = pollster::block_on(instance.enumerate_adapters(wgpu::Backends::all()))
+            .into_iter()
+            .find(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu)?;
+
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()?;
+
+        Some(Gpu { device, queue })
+    })
+    .as_ref()
+}
+
+/// Skips the current test when there's no software adapter.
+macro_rules! gpu_or_skip {
+    () => {
+        match harness::gpu() {
+            Some(gpu) => gpu,
+            None => {
+                eprintln!("no software adapter available, skipping golden test");
+                return;
+            }
+        }
+    };
+}
+pub(crate) use gpu_or_skip;
+
+/// Offscreen target together with a viewport of the same size.
+pub struct Target {
+    pub offscreen: Offscreen,
+    pub viewport: Viewport,
+}
+
+impl Target {
+    pub fn new(gpu: &Gpu, width: u32, height: u32) -> Self {
+        let offscreen = Offscreen::new(&gpu.device, width, height);
+        let mut viewport = Viewport::new(&gpu.device);
+        viewport.update(&gpu.queue, offscreen.resolution());
+
+        Self {
+            offscreen,
+            viewport,
+        }
+    }
+
+    pub fn render(&self, gpu: &Gpu, renderers: Renderers<'_>) -> Image {
+        self.offscreen
+            .render_blocking(&gpu.device, &gpu.queue, &self.viewport, renderers)
+            .expect("failed to render the scene")
+    }
+}
+
+fn goldens_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/goldens")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn save(path: &Path, width: u32, height: u32, data: &[u8]) {
+    image::save_buffer(path, data, width, height, image::ColorType::Rgba8)
+        .unwrap_or_else(|err| panic!("failed to write {}: {err}", path.display()));
+}
+
+/// Compares `actual` against the golden `name`.
+///
+/// On a mismatch the rendered image and a diff, with mismatching pixels in
+/// red over a faded copy of the golden, are written next to each other to
+/// the target directory.
+pub fn assert_golden(name: &str, actual: &Image) {
+    let golden_path = goldens_dir().join(format!("{name}.png"));
+    let (width, height) = actual.size();
+
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::create_dir_all(goldens_dir()).unwrap();
+        save(&golden_path, width, height, actual.data());
+        return;
+    }
+
+    let golden = match image::open(&golden_path) {
+        Ok(golden) => golden.to_rgba8(),
+        Err(err) => panic!(
+            "failed to open golden {}: {err}\nrun with {UPDATE_ENV}=1 to create it",
+            golden_path.display()
+        ),
+    };
+
+    assert_eq!(
+        golden.dimensions(),
+        (width, height),
+        "size of {name} doesn't match its golden"
+    );
+
+    let mut mismatches = 0;
+    let diff = golden
+        .as_raw()
+        .chunks_exact(4)
+        .zip(actual.data().chunks_exact(4))
+        .flat_map(|(expected, actual)| {
+            let matches = expected
+                .iter()
+                .zip(actual)
+                .all(|(e, a)| e.abs_diff(*a) <= TOLERANCE);
+
+            if matches {
+                let luma = (expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 3;
+                let faded = (luma * expected[3] as u32 / 255 / 4) as u8;
+                [faded, faded, faded, 255]
+            } else {
+                mismatches += 1;
+                [255, 0, 0, 255]
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if mismatches == 0 {
+        return;
+    }
+
+    let output_dir = output_dir();
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let actual_path = output_dir.join(format!("{name}.actual.png"));
+    let diff_path = output_dir.join(format!("{name}.diff.png"));
+    save(&actual_path, width, height, actual.data());
+    save(&diff_path, width, height, &diff);
+
+    panic!(
+        "{mismatches} pixels of {name} differ from {} by more than {TOLERANCE}\n\
+         actual: {}\ndiff: {}\nrun with {UPDATE_ENV}=1 if the change is intended",
+        golden_path.display(),
+        actual_path.display(),
+        diff_path.display(),
+    );
+}
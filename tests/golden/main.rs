@@ -0,0 +1,14 @@
+//! Renders scenes on a software adapter and compares them against the PNGs
+//! in `goldens/`. Run with `MOXUI_UPDATE_GOLDENS=1` to regenerate them.
+
+#![cfg(all(
+    feature = "shape_renderer",
+    feature = "texture_renderer",
+    feature = "text_renderer"
+))]
+
+mod harness;
+mod scene;
+mod shapes;
+mod text;
+mod textures;
@@ -0,0 +1,365 @@
+use crate::harness::{self, Target, assert_golden, gpu_or_skip};
+use crate::text::font_system;
+use glyphon::{Attrs, Buffer, Color, Family, Metrics, Shaping, TextBounds};
+use moxui::clip::{Clip, ClipId, ClipStack};
+use moxui::offscreen::Offscreen;
+use moxui::scene::{Primitive, Scene};
+use moxui::shape_renderer::{Shape, ShapeInstance};
+use moxui::text_renderer::{TextArea, TextEffects};
+use moxui::texture_renderer::{self, TextureBounds, Transforms};
+
+#[test]
+fn layered_by_depth() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (160, 64);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14., 18.));
+    buffer.set_text(
+        &mut font_system,
+        "moxui",
+        &Attrs::new().family(Family::Name("Fira Mono")),
+        Shaping::Advanced,
+        None,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    let icon = vec![[40, 120, 220, 255]; 32 * 32].concat();
+    let mut icon_buffer = texture_renderer::Buffer::new(32., 32.);
+    icon_buffer.set_bytes(&icon);
+
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: width,
+        bottom: height,
+    };
+
+    let mut scene = Scene::new(&gpu.device, &gpu.queue, Offscreen::FORMAT, width, height);
+    scene
+        .prepare(
+            &gpu.device,
+            &gpu.queue,
+            &mut font_system,
+            vec![
+                // Given front to back, so only the depth can put them in order
+                Primitive::Text(TextArea {
+                    buffer: &buffer,
+                    left: 52.,
+                    top: 12.,
+                    scale: 1.,
+                    bounds: TextBounds {
+                        left: 0,
+                        top: 0,
+                        right: width as i32,
+                        bottom: height as i32,
+                    },
+                    color: Color::rgb(255, 255, 255),
+                    depth: 0.1,
+                    effects: TextEffects::default(),
+                    clip: ClipId::default(),
+                }),
+                Primitive::Shape(
+                    ShapeInstance {
+                        rect_pos: [24., 24.],
+                        rect_size: [40., 24.],
+                        rect_color: [1., 0.3, 0.3, 0.6],
+                        border_radius: [6.; 4],
+                        depth: 0.3,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                Primitive::Texture(texture_renderer::TextureArea {
+                    left: 12.,
+                    top: 12.,
+                    transforms: Transforms::default(),
+                    bounds,
+                    radius: [6.; 4],
+                    buffer: icon_buffer,
+                    depth: 0.5,
+                    clip: ClipId::default(),
+                }),
+                Primitive::Shape(
+                    ShapeInstance {
+                        rect_pos: [4., 4.],
+                        rect_size: [152., 56.],
+                        rect_color: [0.15, 0.15, 0.2, 1.],
+                        border_radius: [10.; 4],
+                        depth: 0.9,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+            ],
+        )
+        .unwrap();
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    scene.render(&mut encoder, target.offscreen.view()).unwrap();
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let image = target
+        .offscreen
+        .read_blocking(&gpu.device, &gpu.queue)
+        .unwrap();
+
+    assert_golden("scene_layered_by_depth", &image);
+}
+
+#[test]
+fn backdrop_blur() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (160, 64);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    // Vertical stripes, so that the blur is easy to see
+    let mut primitives = (0..20)
+        .map(|i| {
+            let color = match i % 2 {
+                0 => [0.9, 0.3, 0.2, 1.],
+                _ => [0.2, 0.4, 0.9, 1.],
+            };
+            Primitive::Shape(
+                ShapeInstance {
+                    rect_pos: [i as f32 * 8., 0.],
+                    rect_size: [8., height as f32],
+                    rect_color: color,
+                    depth: 0.9,
+                    ..Default::default()
+                }
+                .into(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut panel = Shape::from(ShapeInstance {
+        rect_pos: [8., 8.],
+        rect_size: [64., 48.],
+        rect_color: [1., 1., 1., 0.25],
+        border_radius: [12.; 4],
+        border_size: [1.; 4],
+        border_color: [1., 1., 1., 0.5],
+        depth: 0.5,
+        ..Default::default()
+    });
+    panel.backdrop_blur = 4.;
+    primitives.push(Primitive::Shape(panel));
+
+    let tint = vec![[255, 255, 255, 64]; 32 * 32].concat();
+    let mut tint_buffer = texture_renderer::Buffer::new(32., 32.);
+    tint_buffer.set_bytes(&tint);
+    tint_buffer.set_backdrop_blur(2.);
+    primitives.push(Primitive::Texture(texture_renderer::TextureArea {
+        left: 104.,
+        top: 16.,
+        transforms: Transforms {
+            rotate: 15.,
+            ..Default::default()
+        },
+        bounds: TextureBounds {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: height,
+        },
+        radius: [25.; 4],
+        buffer: tint_buffer,
+        depth: 0.5,
+        clip: ClipId::default(),
+    }));
+
+    let mut scene = Scene::new(&gpu.device, &gpu.queue, Offscreen::FORMAT, width, height);
+    scene
+        .prepare(&gpu.device, &gpu.queue, &mut font_system, primitives)
+        .unwrap();
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    scene.render(&mut encoder, target.offscreen.view()).unwrap();
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let image = target
+        .offscreen
+        .read_blocking(&gpu.device, &gpu.queue)
+        .unwrap();
+
+    assert_golden("scene_backdrop_blur", &image);
+}
+
+#[test]
+fn large_backdrop_blur() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (160, 64);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    // Wide stripes, which only a blur of this size smooths out
+    let mut primitives = (0..5)
+        .map(|i| {
+            let color = match i % 2 {
+                0 => [0.9, 0.3, 0.2, 1.],
+                _ => [0.2, 0.4, 0.9, 1.],
+            };
+            Primitive::Shape(
+                ShapeInstance {
+                    rect_pos: [i as f32 * 32., 0.],
+                    rect_size: [32., height as f32],
+                    rect_color: color,
+                    depth: 0.9,
+                    ..Default::default()
+                }
+                .into(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Blurred on a downsampled copy of the target
+    let mut panel = Shape::from(ShapeInstance {
+        rect_pos: [16., 8.],
+        rect_size: [128., 48.],
+        rect_color: [1., 1., 1., 0.1],
+        border_radius: [16.; 4],
+        depth: 0.5,
+        ..Default::default()
+    });
+    panel.backdrop_blur = 24.;
+    primitives.push(Primitive::Shape(panel));
+
+    let mut scene = Scene::new(&gpu.device, &gpu.queue, Offscreen::FORMAT, width, height);
+    scene
+        .prepare(&gpu.device, &gpu.queue, &mut font_system, primitives)
+        .unwrap();
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    scene.render(&mut encoder, target.offscreen.view()).unwrap();
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let image = target
+        .offscreen
+        .read_blocking(&gpu.device, &gpu.queue)
+        .unwrap();
+
+    assert_golden("scene_large_backdrop_blur", &image);
+}
+
+#[test]
+fn clipped() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (160, 64);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14., 18.));
+    buffer.set_text(
+        &mut font_system,
+        "clipped text",
+        &Attrs::new().family(Family::Name("Fira Mono")),
+        Shaping::Advanced,
+        None,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    let mut corner = Buffer::new(&mut font_system, Metrics::new(24., 24.));
+    corner.set_text(
+        &mut font_system,
+        "MMMM",
+        &Attrs::new().family(Family::Name("Fira Mono")),
+        Shaping::Advanced,
+        None,
+    );
+    corner.shape_until_scroll(&mut font_system, false);
+
+    let icon = vec![[40, 120, 220, 255]; 32 * 32].concat();
+    let mut icon_buffer = texture_renderer::Buffer::new(32., 32.);
+    icon_buffer.set_bytes(&icon);
+
+    // A rounded container with a list inside that starts below its header
+    let mut clips = ClipStack::new();
+    let container = clips.push(Clip::rounded(8., 8., 144., 48., [16.; 4]));
+    let list = clips.push(Clip::rect(8., 24., 144., 32.));
+    clips.pop();
+    clips.pop();
+
+    let mut scene = Scene::new(&gpu.device, &gpu.queue, Offscreen::FORMAT, width, height);
+    scene.prepare_clips(&gpu.device, &gpu.queue, &clips);
+    scene
+        .prepare(
+            &gpu.device,
+            &gpu.queue,
+            &mut font_system,
+            vec![
+                Primitive::Shape(
+                    ShapeInstance {
+                        rect_pos: [0., 0.],
+                        rect_size: [width as f32, height as f32],
+                        rect_color: [0.2, 0.2, 0.25, 1.],
+                        depth: 0.9,
+                        clip: container,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                Primitive::Shape(
+                    ShapeInstance {
+                        rect_pos: [0., 16.],
+                        rect_size: [80., 24.],
+                        rect_color: [1., 0.3, 0.3, 1.],
+                        depth: 0.5,
+                        clip: list,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                Primitive::Texture(texture_renderer::TextureArea {
+                    left: 128.,
+                    top: 4.,
+                    transforms: Transforms::default(),
+                    bounds: TextureBounds {
+                        left: 0,
+                        top: 0,
+                        right: width,
+                        bottom: height,
+                    },
+                    radius: [0.; 4],
+                    buffer: icon_buffer,
+                    depth: 0.5,
+                    clip: list,
+                }),
+                Primitive::Text(TextArea {
+                    bounds: clips.text_bounds(list),
+                    depth: 0.1,
+                    clip: list,
+                    ..TextArea::new(&buffer, 84., 18.)
+                }),
+                // Across the rounded bottom right corner of the container
+                Primitive::Text(TextArea {
+                    bounds: clips.text_bounds(container),
+                    depth: 0.1,
+                    clip: container,
+                    ..TextArea::new(&corner, 104., 38.)
+                }),
+            ],
+        )
+        .unwrap();
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    scene.render(&mut encoder, target.offscreen.view()).unwrap();
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let image = target
+        .offscreen
+        .read_blocking(&gpu.device, &gpu.queue)
+        .unwrap();
+
+    assert_golden("scene_clipped", &image);
+}
@@ -0,0 +1,205 @@
+use crate::harness::{self, Target, assert_golden, gpu_or_skip};
+use moxui::offscreen::{Offscreen, Renderers};
+use moxui::shape_renderer::{GradientStop, Paint, Shape, ShapeInstance, ShapeRenderer};
+
+fn render(name: &str, width: u32, height: u32, shapes: &[Shape]) {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, width, height);
+
+    let mut renderer = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    renderer.prepare_shapes(&gpu.device, &gpu.queue, shapes);
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&renderer),
+            ..Default::default()
+        },
+    );
+
+    assert_golden(name, &image);
+}
+
+#[test]
+fn rounded_rects() {
+    let shapes = [
+        ShapeInstance {
+            rect_pos: [8., 8.],
+            rect_size: [48., 48.],
+            rect_color: [1., 0.2, 0.2, 1.],
+            ..Default::default()
+        },
+        ShapeInstance {
+            rect_pos: [72., 8.],
+            rect_size: [48., 48.],
+            rect_color: [0.2, 0.6, 1., 1.],
+            border_radius: [24.; 4],
+            ..Default::default()
+        },
+        ShapeInstance {
+            rect_pos: [136., 8.],
+            rect_size: [46., 46.],
+            rect_color: [0.2, 0.8, 0.3, 1.],
+            border_radius: [0., 8., 16., 24.],
+            border_size: [1., 1., 1., 1.],
+            border_color: [1., 1., 1., 1.],
+            ..Default::default()
+        },
+        ShapeInstance {
+            rect_pos: [8., 72.],
+            rect_size: [104., 40.],
+            rect_color: [1., 1., 1., 0.5],
+            border_radius: [12.; 4],
+            border_size: [6., 2., 4., 8.],
+            border_color: [0.9, 0.7, 0., 1.],
+            ..Default::default()
+        },
+        ShapeInstance {
+            rect_pos: [64., 48.],
+            rect_size: [30., 20.],
+            rect_color: [0.5, 0., 1., 1.],
+            border_radius: [6.; 4],
+            scale: 2.,
+            depth: 0.5,
+            ..Default::default()
+        },
+    ]
+    .map(Shape::from);
+
+    render("rounded_rects", 192, 128, &shapes);
+}
+
+#[test]
+fn box_shadows() {
+    let shapes = [
+        ShapeInstance {
+            rect_pos: [24., 24.],
+            rect_size: [64., 64.],
+            rect_color: [1., 1., 1., 1.],
+            border_radius: [12.; 4],
+            shadow_offset: [4., 6.],
+            shadow_blur: 12.,
+            shadow_spread: 2.,
+            shadow_color: [0., 0., 0., 0.8],
+            ..Default::default()
+        },
+        ShapeInstance {
+            rect_pos: [112., 24.],
+            rect_size: [64., 64.],
+            rect_color: [0.9, 0.9, 0.9, 1.],
+            border_radius: [12.; 4],
+            border_size: [2.; 4],
+            border_color: [0.2, 0.2, 0.2, 1.],
+            shadow_offset: [3., 3.],
+            shadow_blur: 8.,
+            shadow_color: [0., 0., 0.5, 1.],
+            shadow_inset: 1,
+            ..Default::default()
+        },
+    ]
+    .map(Shape::from);
+
+    render("box_shadows", 200, 112, &shapes);
+}
+
+#[test]
+fn gradients() {
+    let stops = vec![
+        GradientStop::new(0., [1., 0., 0., 1.]),
+        GradientStop::new(0.5, [0., 1., 0., 1.]),
+        GradientStop::new(1., [0., 0., 1., 1.]),
+    ];
+
+    let shape = |x: f32, fill: Paint| Shape {
+        instance: ShapeInstance {
+            rect_pos: [x, 8.],
+            rect_size: [56., 56.],
+            border_radius: [10.; 4],
+            border_size: [4.; 4],
+            ..Default::default()
+        },
+        fill,
+        border: Paint::Linear {
+            angle: 0.,
+            stops: vec![
+                GradientStop::new(0., [1., 1., 1., 1.]),
+                GradientStop::new(1., [0., 0., 0., 1.]),
+            ],
+        },
+        backdrop_blur: 0.,
+    };
+
+    let shapes = [
+        shape(
+            8.,
+            Paint::Linear {
+                angle: 45.,
+                stops: stops.clone(),
+            },
+        ),
+        shape(
+            80.,
+            Paint::Radial {
+                center: [0.3, 0.3],
+                radius: [0.8, 0.6],
+                stops: stops.clone(),
+            },
+        ),
+        shape(
+            152.,
+            Paint::Conic {
+                center: [0.5, 0.5],
+                angle: 30.,
+                stops,
+            },
+        ),
+    ];
+
+    render("gradients", 224, 80, &shapes);
+}
+
+#[test]
+fn async_readback() {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, 32, 32);
+
+    let mut renderer = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    renderer.prepare_shapes(
+        &gpu.device,
+        &gpu.queue,
+        &[ShapeInstance {
+            rect_pos: [4., 4.],
+            rect_size: [24., 24.],
+            rect_color: [1., 0.2, 0.2, 1.],
+            border_radius: [6.; 4],
+            ..Default::default()
+        }
+        .into()],
+    );
+    let renderers = || Renderers {
+        shapes: Some(&renderer),
+        ..Default::default()
+    };
+
+    // The future only resolves while something else polls the device
+    let done = std::sync::atomic::AtomicBool::new(false);
+    let image = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                gpu.device.poll(wgpu::PollType::Poll).unwrap();
+                std::thread::yield_now();
+            }
+        });
+
+        let image = pollster::block_on(target.offscreen.render(
+            &gpu.device,
+            &gpu.queue,
+            &target.viewport,
+            renderers(),
+        ));
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        image.unwrap()
+    });
+
+    assert_eq!(image, target.render(gpu, renderers()));
+}
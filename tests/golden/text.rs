@@ -0,0 +1,579 @@
+use crate::harness::{self, Target, assert_golden, gpu_or_skip};
+use glyphon::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, TextBounds};
+use moxui::clip::{Clip, ClipId, ClipStack};
+use moxui::offscreen::{Offscreen, Renderers};
+use moxui::shape_renderer::{ShapeInstance, ShapeRenderer};
+use moxui::text_renderer::{
+    RichText, TextArea, TextEffects, TextGlow, TextLayout, TextOutline, TextRenderer, TextShadow,
+    VerticalAlign, measure,
+};
+
+/// Font system with only the bundled font, so that the system's fonts don't
+/// leak into the goldens.
+pub fn font_system() -> FontSystem {
+    let mut db = glyphon::fontdb::Database::new();
+    db.load_font_data(include_bytes!("fonts/FiraMono-Medium.ttf").to_vec());
+
+    FontSystem::new_with_locale_and_db("en-US".into(), db)
+}
+
+#[test]
+fn text_over_shape() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (200, 56);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(16., 20.));
+    buffer.set_size(&mut font_system, Some(184.), Some(40.));
+    buffer.set_text(
+        &mut font_system,
+        "Hello, moxui!\nfn main() {}",
+        &Attrs::new().family(Family::Name("Fira Mono")),
+        Shaping::Advanced,
+        None,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    let mut shapes = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shapes.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &[ShapeInstance {
+            rect_pos: [4., 4.],
+            rect_size: [192., 48.],
+            rect_color: [0.15, 0.15, 0.2, 1.],
+            border_radius: [8.; 4],
+            depth: 0.5,
+            ..Default::default()
+        }],
+    );
+
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &[TextArea {
+            buffer: &buffer,
+            left: 8.,
+            top: 8.,
+            scale: 1.,
+            bounds: TextBounds {
+                left: 0,
+                top: 0,
+                right: width as i32,
+                bottom: height as i32,
+            },
+            color: Color::rgb(240, 240, 240),
+            depth: 0.,
+            effects: TextEffects::default(),
+            clip: ClipId::default(),
+        }],
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shapes),
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("text_over_shape", &image);
+}
+
+#[test]
+fn depth_per_area() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (120, 48);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(24., 28.));
+    buffer.set_text(
+        &mut font_system,
+        "depth",
+        &Attrs::new().family(Family::Name("Fira Mono")),
+        Shaping::Advanced,
+        None,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    let mut shapes = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shapes.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &[ShapeInstance {
+            rect_pos: [40., 0.],
+            rect_size: [40., 48.],
+            rect_color: [0.2, 0.4, 0.9, 1.],
+            depth: 0.5,
+            ..Default::default()
+        }],
+    );
+
+    // The same renderer draws one line behind the shape and one in front
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &[
+            TextArea {
+                color: Color::rgb(255, 80, 80),
+                depth: 0.8,
+                ..TextArea::new(&buffer, 4., -2.)
+            },
+            TextArea {
+                depth: 0.2,
+                ..TextArea::new(&buffer, 28., 20.)
+            },
+        ],
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shapes),
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("text_depth_per_area", &image);
+}
+
+#[test]
+fn layout() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (200, 120);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+    let attrs = Attrs::new().family(Family::Name("Fira Mono"));
+
+    // Two lines, then an ellipsis, centered in a box taller than them
+    let body = TextLayout {
+        width: Some(184.),
+        height: Some(56.),
+        max_lines: Some(2),
+        align: Some(glyphon::cosmic_text::Align::Center),
+        vertical_align: VerticalAlign::Center,
+        ..Default::default()
+    };
+    let mut body_buffer = Buffer::new(&mut font_system, Metrics::new(14., 18.));
+    body.set_text(
+        &mut font_system,
+        &mut body_buffer,
+        "A notification body that is far too long to fit into two lines of its box",
+        &attrs,
+    );
+
+    // A single line cut off at the width of its box
+    let summary = TextLayout {
+        width: Some(120.),
+        wrap: glyphon::Wrap::None,
+        ..Default::default()
+    };
+    let mut summary_buffer = Buffer::new(&mut font_system, Metrics::new(14., 18.));
+    summary.set_text(
+        &mut font_system,
+        &mut summary_buffer,
+        "Summary without wrapping",
+        &attrs,
+    );
+
+    // A label whose background is sized to the measured text
+    let label = TextLayout::default();
+    let mut label_buffer = Buffer::new(&mut font_system, Metrics::new(14., 18.));
+    label.set_text(&mut font_system, &mut label_buffer, "measured", &attrs);
+    let [label_width, label_height] = measure(&label_buffer);
+
+    let mut shapes = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shapes.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &[
+            ShapeInstance {
+                rect_pos: [8., 8.],
+                rect_size: [184., 56.],
+                rect_color: [0.15, 0.15, 0.2, 1.],
+                depth: 0.5,
+                ..Default::default()
+            },
+            ShapeInstance {
+                rect_pos: [8., 72.],
+                rect_size: [120., 18.],
+                rect_color: [0.15, 0.15, 0.2, 1.],
+                depth: 0.5,
+                ..Default::default()
+            },
+            ShapeInstance {
+                rect_pos: [8., 96.],
+                rect_size: [label_width, label_height],
+                rect_color: [0.2, 0.4, 0.9, 1.],
+                depth: 0.5,
+                ..Default::default()
+            },
+        ],
+    );
+
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &[
+            body.area(&body_buffer, 8., 8.),
+            summary.area(&summary_buffer, 8., 72.),
+            label.area(&label_buffer, 8., 96.),
+        ],
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shapes),
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("text_layout", &image);
+}
+
+#[test]
+fn rich_text() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (200, 64);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let body = RichText::from_markup(
+        "<b>Build</b> <i>failed</i> &amp; <u>retried</u>, <unknown>see</unknown> \
+         <a href=\"https://ci.example/log\">the <b>log</b></a> <img src=\"x.png\" alt=\"[x]\"/> \
+         and more text that doesn't fit",
+    );
+    assert_eq!(
+        body.text(),
+        "Build failed & retried, see the log [x] and more text that doesn't fit"
+    );
+    assert_eq!(
+        body.spans[6].link.as_deref(),
+        Some("https://ci.example/log")
+    );
+    assert_eq!(body.spans[9].image.as_deref(), Some("x.png"));
+
+    let layout = TextLayout {
+        width: Some(184.),
+        max_lines: Some(2),
+        ..Default::default()
+    };
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(14., 18.));
+    layout.set_rich_text(
+        &mut font_system,
+        &mut buffer,
+        &body,
+        &Attrs::new().family(Family::Name("Fira Mono")),
+    );
+
+    let area = TextArea {
+        depth: 0.1,
+        ..layout.area(&buffer, 8., 8.)
+    };
+
+    // Links are hit-tested per line, and drawn behind the text here
+    let links = body.links(&area);
+    assert!(!links.is_empty());
+    let [left, top, link_width, link_height] = links[0].1;
+    assert_eq!(
+        body.link_at(&area, left + link_width / 2., top + link_height / 2.),
+        Some("https://ci.example/log")
+    );
+    assert_eq!(body.link_at(&area, 9., 9.), None);
+
+    let mut shapes = vec![ShapeInstance {
+        rect_pos: [0., 0.],
+        rect_size: [width as f32, height as f32],
+        rect_color: [0.15, 0.15, 0.2, 1.],
+        depth: 0.9,
+        ..Default::default()
+    }];
+    shapes.extend(body.underlines(&area));
+    shapes.extend(
+        links
+            .iter()
+            .map(|(_, [left, top, width, height])| ShapeInstance {
+                rect_pos: [*left, *top],
+                rect_size: [*width, *height],
+                rect_color: [0.2, 0.4, 0.9, 1.],
+                depth: 0.5,
+                ..Default::default()
+            }),
+    );
+
+    let mut shape_renderer = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shape_renderer.prepare(&gpu.device, &gpu.queue, &shapes);
+
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &[area],
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shape_renderer),
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("text_rich_text", &image);
+}
+
+#[test]
+fn selection() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (200, 56);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(16., 20.));
+    buffer.set_text(
+        &mut font_system,
+        "Reply to the\nnotification",
+        &Attrs::new().family(Family::Name("Fira Mono")),
+        Shaping::Advanced,
+        None,
+    );
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    let area = TextArea {
+        depth: 0.1,
+        ..TextArea::new(&buffer, 8., 8.)
+    };
+
+    // From within "Reply" on the first line to within "notification" on
+    // the second, as if dragged with the pointer
+    let start = area.hit(30., 16.).unwrap();
+    let end = area.hit(60., 36.).unwrap();
+    assert_eq!((start.line, end.line), (0, 1));
+    assert_eq!(area.index(start), start.index);
+    assert_eq!(area.index(end), "Reply to the\n".len() + end.index);
+
+    let caret = area.caret(end).unwrap();
+    assert_eq!(area.hit(caret[0], caret[1] + 1.).unwrap().index, end.index);
+
+    let mut shapes = vec![ShapeInstance {
+        rect_pos: [0., 0.],
+        rect_size: [width as f32, height as f32],
+        rect_color: [0.15, 0.15, 0.2, 1.],
+        depth: 0.9,
+        ..Default::default()
+    }];
+    shapes.extend(area.selection_shapes(start, end, [0.2, 0.4, 0.9, 1.]));
+    shapes.push(ShapeInstance {
+        rect_pos: [caret[0], caret[1]],
+        rect_size: [caret[2], caret[3]],
+        rect_color: [1., 0.8, 0.2, 1.],
+        depth: 0.05,
+        ..Default::default()
+    });
+
+    let mut shape_renderer = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shape_renderer.prepare(&gpu.device, &gpu.queue, &shapes);
+
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &[area],
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shape_renderer),
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("text_selection", &image);
+}
+
+#[test]
+fn effects() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (200, 96);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let attrs = Attrs::new().family(Family::Name("Fira Mono"));
+    let buffers = ["Shadow", "Outline", "Glow"].map(|text| {
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(20., 24.));
+        buffer.set_text(&mut font_system, text, &attrs, Shaping::Advanced, None);
+        buffer.shape_until_scroll(&mut font_system, false);
+        buffer
+    });
+
+    // Light and dark stripes, which plain white text would get lost on
+    let shapes = (0..4)
+        .map(|i| ShapeInstance {
+            rect_pos: [i as f32 * 50., 0.],
+            rect_size: [50., height as f32],
+            rect_color: match i % 2 {
+                0 => [0.9, 0.9, 0.85, 1.],
+                _ => [0.2, 0.25, 0.3, 1.],
+            },
+            depth: 0.9,
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut shape_renderer = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shape_renderer.prepare(&gpu.device, &gpu.queue, &shapes);
+
+    let areas = [
+        TextEffects {
+            shadow: Some(TextShadow {
+                offset: [2., 2.],
+                blur: 4.,
+                color: Color::rgba(0, 0, 0, 220),
+            }),
+            ..Default::default()
+        },
+        TextEffects {
+            outline: Some(TextOutline {
+                width: 2.,
+                color: Color::rgb(0, 0, 0),
+            }),
+            ..Default::default()
+        },
+        TextEffects {
+            glow: Some(TextGlow {
+                radius: 6.,
+                color: Color::rgb(255, 60, 200),
+            }),
+            ..Default::default()
+        },
+    ]
+    .into_iter()
+    .zip(&buffers)
+    .enumerate()
+    .map(|(i, (effects, buffer))| TextArea {
+        depth: 0.1,
+        effects,
+        ..TextArea::new(buffer, 24. + i as f32 * 16., 4. + i as f32 * 30.)
+    })
+    .collect::<Vec<_>>();
+
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &areas,
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shape_renderer),
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("text_effects", &image);
+}
+
+#[test]
+fn effects_past_the_texture_limit() {
+    let gpu = gpu_or_skip!();
+    let (width, height) = (64, 64);
+    let target = Target::new(gpu, width, height);
+    let mut font_system = font_system();
+
+    let attrs = Attrs::new().family(Family::Name("Fira Mono"));
+    let mut buffer = Buffer::new(&mut font_system, Metrics::new(20., 24.));
+    buffer.set_text(&mut font_system, "#", &attrs, Shaping::Advanced, None);
+    buffer.shape_until_scroll(&mut font_system, false);
+
+    let mut clips = ClipStack::new();
+    let left = clips.push(Clip::rect(0., 0., 32., height as f32));
+    clips.pop();
+    let right = clips.push(Clip::rect(32., 0., 32., height as f32));
+    clips.pop();
+
+    // Invisible glows that reach over the whole target, so that every area
+    // takes up a layer of the effects texture of its own
+    let glow = TextEffects {
+        glow: Some(TextGlow {
+            radius: 64.,
+            color: Color::rgba(0, 0, 0, 0),
+        }),
+        ..Default::default()
+    };
+    let layers = gpu.device.limits().max_texture_dimension_2d / height;
+    let mut areas = (0..=layers)
+        .map(|_| TextArea {
+            depth: 0.5,
+            clip: left,
+            effects: glow,
+            ..TextArea::new(&buffer, 4., 4.)
+        })
+        .collect::<Vec<_>>();
+    // The frontmost area is the last to be packed, so it's past the limit
+    areas.push(TextArea {
+        depth: 0.1,
+        clip: right,
+        effects: glow,
+        ..TextArea::new(&buffer, 40., 4.)
+    });
+
+    let mut text = TextRenderer::new(&gpu.device, &gpu.queue, Offscreen::FORMAT);
+    text.prepare_clips(&gpu.device, &gpu.queue, &clips);
+    text.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &target.viewport,
+        &areas,
+        &mut font_system,
+    )
+    .unwrap();
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            text: Some(&text),
+            ..Default::default()
+        },
+    );
+
+    // Drawn without its effects rather than not at all
+    let drawn = image
+        .data()
+        .chunks_exact(4 * width as usize)
+        .flat_map(|row| row[32 * 4..].chunks_exact(4))
+        .any(|pixel| pixel[3] > 128);
+    assert!(drawn, "the area past the limit wasn't drawn");
+}
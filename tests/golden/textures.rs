@@ -0,0 +1,397 @@
+use crate::harness::{self, Target, assert_golden, gpu_or_skip};
+use moxui::clip::ClipId;
+use moxui::image::{AnimatedImage, Frame, Image};
+use moxui::offscreen::{Offscreen, Renderers};
+use moxui::shape_renderer::{ShapeInstance, ShapeRenderer};
+use moxui::texture_renderer::{Buffer, TextureArea, TextureBounds, TextureRenderer, Transforms};
+use std::time::{Duration, Instant};
+
+const SIZE: u32 = 32;
+
+/// Red grows to the right, green downwards, so that every filter has a
+/// visible effect somewhere in the image.
+fn pattern() -> Vec<u8> {
+    (0..SIZE)
+        .flat_map(|y| (0..SIZE).map(move |x| [x as u8 * 8, y as u8 * 8, 160, 255]))
+        .flatten()
+        .collect()
+}
+
+fn area<'a>(
+    left: f32,
+    top: f32,
+    bounds: &TextureBounds,
+    bytes: &'a [u8],
+    configure: impl FnOnce(&mut Buffer<'a>),
+) -> TextureArea<'a> {
+    let mut buffer = Buffer::new(SIZE as f32, SIZE as f32);
+    buffer.set_bytes(bytes);
+    configure(&mut buffer);
+
+    TextureArea {
+        left,
+        top,
+        transforms: Transforms::default(),
+        bounds: bounds.clone(),
+        radius: [0.; 4],
+        buffer,
+        depth: 0.,
+        clip: ClipId::default(),
+    }
+}
+
+fn render(name: &str, width: u32, height: u32, areas: &[TextureArea]) {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, width, height);
+
+    // The GL backend only treats textures with several layers as arrays
+    let mut renderer =
+        TextureRenderer::with_layers(&gpu.device, Offscreen::FORMAT, 64, width, height, 2);
+    renderer.prepare(&gpu.device, &gpu.queue, areas);
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            textures: Some(&renderer),
+            ..Default::default()
+        },
+    );
+
+    assert_golden(name, &image);
+}
+
+#[test]
+fn filters() {
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 160,
+        bottom: 80,
+    };
+
+    let areas = [
+        area(4., 4., &bounds, &bytes, |_| {}),
+        area(44., 4., &bounds, &bytes, |b| b.set_grayscale(1.)),
+        area(84., 4., &bounds, &bytes, |b| b.set_sepia(1.)),
+        area(124., 4., &bounds, &bytes, |b| b.set_invert(1.)),
+        area(4., 44., &bounds, &bytes, |b| b.set_hue_rotate(90.)),
+        area(44., 44., &bounds, &bytes, |b| b.set_saturation(2.)),
+        area(84., 44., &bounds, &bytes, |b| {
+            b.set_brightness(0.2);
+            b.set_contrast(0.5);
+        }),
+        area(124., 44., &bounds, &bytes, |b| b.set_opacity(0.5)),
+    ];
+
+    render("filters", 160, 80, &areas);
+}
+
+#[test]
+fn radius_shadow_and_transforms() {
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 160,
+        bottom: 64,
+    };
+
+    let mut rounded = area(8., 8., &bounds, &bytes, |b| {
+        b.set_shadow(3., 3., 6., 0.);
+        b.set_shadow_color(0., 0., 0., 0.8);
+    });
+    rounded.radius = [8.; 4];
+
+    let mut rotated = area(64., 16., &bounds, &bytes, |_| {});
+    rotated.transforms = Transforms {
+        rotate: 30.,
+        scale_x: 1.2,
+        ..Default::default()
+    };
+
+    let mut clipped = area(120., 16., &bounds, &bytes, |_| {});
+    clipped.bounds = TextureBounds {
+        left: 120,
+        top: 16,
+        right: 140,
+        bottom: 36,
+    };
+
+    render(
+        "radius_shadow_and_transforms",
+        160,
+        64,
+        &[rounded, rotated, clipped],
+    );
+}
+
+#[test]
+fn blur() {
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 144,
+        bottom: 48,
+    };
+
+    // The last one is large enough to be blurred at a lower resolution
+    let areas = [
+        area(8., 8., &bounds, &bytes, |_| {}),
+        area(56., 8., &bounds, &bytes, |b| b.set_blur(4.)),
+        area(104., 8., &bounds, &bytes, |b| b.set_blur(20.)),
+    ];
+
+    render("blur", 144, 48, &areas);
+}
+
+#[test]
+fn blur_reprepared() {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, 144, 48);
+
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 144,
+        bottom: 48,
+    };
+
+    let mut renderer = TextureRenderer::with_layers(&gpu.device, Offscreen::FORMAT, 64, 144, 48, 2);
+
+    // Enough kernels to outgrow the initial buffers, none of which the
+    // next frame uses
+    let previous = (1..8)
+        .map(|i| area(8., 8., &bounds, &bytes, |b| b.set_blur(i as f32 + 0.5)))
+        .collect::<Vec<_>>();
+    renderer.prepare(&gpu.device, &gpu.queue, &previous);
+
+    let areas = [
+        area(8., 8., &bounds, &bytes, |_| {}),
+        area(56., 8., &bounds, &bytes, |b| b.set_blur(4.)),
+        area(104., 8., &bounds, &bytes, |b| b.set_blur(20.)),
+    ];
+    renderer.prepare(&gpu.device, &gpu.queue, &areas);
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            textures: Some(&renderer),
+            ..Default::default()
+        },
+    );
+
+    // Matches a renderer that only ever blurred this frame
+    assert_golden("blur", &image);
+}
+
+#[test]
+fn blur_per_axis() {
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 144,
+        bottom: 48,
+    };
+
+    // Horizontal only, vertical only and a fractional sigma
+    let areas = [
+        area(8., 8., &bounds, &bytes, |b| b.set_blur_xy(6., 0.)),
+        area(56., 8., &bounds, &bytes, |b| b.set_blur_xy(0., 6.)),
+        area(104., 8., &bounds, &bytes, |b| b.set_blur(1.5)),
+    ];
+
+    render("blur_per_axis", 144, 48, &areas);
+}
+
+#[test]
+fn blur_motion() {
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 144,
+        bottom: 48,
+    };
+
+    // Large along one axis only, which still runs at a lower resolution
+    let areas = [area(56., 8., &bounds, &bytes, |b| b.set_blur_xy(24., 0.))];
+
+    render("blur_motion", 144, 48, &areas);
+}
+
+#[test]
+fn depth_with_shapes() {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, 96, 48);
+
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 96,
+        bottom: 48,
+    };
+
+    let mut shapes = ShapeRenderer::new(&gpu.device, Offscreen::FORMAT);
+    shapes.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &[ShapeInstance {
+            rect_pos: [24., 12.],
+            rect_size: [48., 24.],
+            rect_color: [1., 1., 1., 1.],
+            depth: 0.5,
+            ..Default::default()
+        }],
+    );
+
+    // Drawn after the shape, but the first one is behind it
+    let mut behind = area(8., 8., &bounds, &bytes, |_| {});
+    behind.depth = 0.8;
+    let mut in_front = area(56., 8., &bounds, &bytes, |b| b.set_blur(2.));
+    in_front.depth = 0.2;
+
+    let mut textures = TextureRenderer::with_layers(&gpu.device, Offscreen::FORMAT, 64, 96, 48, 2);
+    textures.prepare(&gpu.device, &gpu.queue, &[behind, in_front]);
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            shapes: Some(&shapes),
+            textures: Some(&textures),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("depth_with_shapes", &image);
+}
+
+#[test]
+fn animated_frames_stay_resident() {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, 80, 48);
+
+    // The pattern, then the pattern with inverted colors
+    let inverted = pattern()
+        .chunks(4)
+        .flat_map(|c| [255 - c[0], 255 - c[1], 255 - c[2], c[3]])
+        .collect();
+    let frames = [pattern(), inverted].map(|data| Frame {
+        image: Image::from_raw(SIZE, SIZE, data).unwrap(),
+        delay: Duration::from_millis(100),
+    });
+    let mut animation = AnimatedImage::new(frames.into());
+
+    let mut renderer = TextureRenderer::with_layers(&gpu.device, Offscreen::FORMAT, 64, 80, 48, 2);
+    let start = Instant::now();
+    animation.play(start);
+
+    let at = |animation: &mut AnimatedImage, renderer: &mut TextureRenderer, ms| {
+        animation
+            .texture(renderer, &gpu.queue, start + Duration::from_millis(ms))
+            .unwrap()
+    };
+
+    // Every upload gets a new handle, so equal handles mean no re-upload
+    let first = at(&mut animation, &mut renderer, 0);
+    assert_eq!(at(&mut animation, &mut renderer, 50), first);
+    let second = at(&mut animation, &mut renderer, 150);
+    assert_ne!(second, first);
+    assert_eq!(at(&mut animation, &mut renderer, 210), first);
+    assert_eq!(at(&mut animation, &mut renderer, 320), second);
+
+    // Evicted frames are uploaded again
+    renderer.remove(first);
+    let reuploaded = at(&mut animation, &mut renderer, 400);
+    assert!(renderer.contains(reuploaded) && reuploaded != first);
+
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 80,
+        bottom: 48,
+    };
+    renderer.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &[
+            area(8., 8., &bounds, &[], |b| b.set_texture(reuploaded)),
+            area(40., 8., &bounds, &[], |b| b.set_texture(second)),
+        ],
+    );
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            textures: Some(&renderer),
+            ..Default::default()
+        },
+    );
+
+    assert_golden("textures_animated", &image);
+}
+
+#[test]
+fn short_data_is_rejected() {
+    let gpu = gpu_or_skip!();
+    let target = Target::new(gpu, 80, 48);
+
+    let mut renderer = TextureRenderer::with_layers(&gpu.device, Offscreen::FORMAT, 64, 80, 48, 2);
+    let data = pattern();
+    let short = &data[..data.len() - 4];
+    assert!(renderer.upload(&gpu.queue, SIZE, SIZE, short).is_err());
+
+    // Fill the atlas with the pattern, so that anything drawn from stale
+    // atlas contents shows up
+    let texture = renderer.upload(&gpu.queue, SIZE, SIZE, &data).unwrap();
+    renderer.remove(texture);
+
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 80,
+        bottom: 48,
+    };
+    renderer.prepare(
+        &gpu.device,
+        &gpu.queue,
+        &[area(8., 8., &bounds, short, |_| {})],
+    );
+
+    let image = target.render(
+        gpu,
+        Renderers {
+            textures: Some(&renderer),
+            ..Default::default()
+        },
+    );
+
+    assert!(image.data().iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn blur_mixed_near_edges() {
+    let bytes = pattern();
+    let bounds = TextureBounds {
+        left: 0,
+        top: 0,
+        right: 144,
+        bottom: 48,
+    };
+
+    // Unblurred textures between blurred ones, and blurred regions that
+    // reach past the target, at full and at lower resolutions
+    let areas = [
+        area(-12., 4., &bounds, &bytes, |b| b.set_blur(20.)),
+        area(24., 8., &bounds, &bytes, |_| {}),
+        area(60., 24., &bounds, &bytes, |b| b.set_blur(3.)),
+        area(76., -8., &bounds, &bytes, |_| {}),
+        area(124., 28., &bounds, &bytes, |b| b.set_blur(40.)),
+    ];
+
+    render("blur_mixed_near_edges", 144, 48, &areas);
+}
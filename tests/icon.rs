@@ -0,0 +1,237 @@
+use moxui::icon::IconLookup;
+use std::path::{Path, PathBuf};
+
+/// Fake icon theme tree in a temporary directory, removed on drop.
+struct Themes {
+    root: PathBuf,
+}
+
+impl Themes {
+    fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("moxui-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        Self { root }
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> PathBuf {
+        let path = self.root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// A `size`x`size` PNG at `path`.
+    fn icon(&self, path: &str, size: u32) -> PathBuf {
+        self.pixmap(path, size, size)
+    }
+
+    /// A `width`x`height` PNG at `path`.
+    fn pixmap(&self, path: &str, width: u32, height: u32) -> PathBuf {
+        let mut bytes = Vec::new();
+        image::RgbaImage::from_pixel(width, height, image::Rgba([40, 120, 200, 255]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        self.write(path, &bytes)
+    }
+
+    fn lookup(&self) -> IconLookup {
+        IconLookup::with_base_dirs(
+            ["home", "share", "pixmaps"]
+                .map(|dir| self.root.join(dir))
+                .into(),
+        )
+    }
+}
+
+impl Drop for Themes {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// `Custom` inherits from `Base`, which inherits from `Custom` again.
+fn themes(name: &str) -> Themes {
+    let themes = Themes::new(name);
+
+    themes.write(
+        "home/Custom/index.theme",
+        b"[Icon Theme]
+Name=Custom
+# Comments and unknown keys are skipped
+Comment=Test theme
+Inherits=Base
+Directories=48x48/apps,scalable/apps
+ScaledDirectories=48x48@2/apps
+
+[48x48/apps]
+Size=48
+Type=Fixed
+
+[48x48@2/apps]
+Size=48
+Scale=2
+Type=Fixed
+
+[scalable/apps]
+Size=48
+MinSize=16
+MaxSize=256
+Type=Scalable
+",
+    );
+    themes.write(
+        "share/Base/index.theme",
+        b"[Icon Theme]
+Name=Base
+Inherits=Custom
+Directories=16x16/apps,32x32/apps
+
+[16x16/apps]
+Size=16
+
+[32x32/apps]
+Size=32
+",
+    );
+    themes.write(
+        "share/hicolor/index.theme",
+        b"[Icon Theme]
+Name=Hicolor
+Directories=48x48/apps
+
+[48x48/apps]
+Size=48
+Type=Fixed
+",
+    );
+
+    themes.icon("home/Custom/48x48/apps/custom.png", 48);
+    themes.icon("home/Custom/48x48@2/apps/custom.png", 96);
+    // Parts of a theme can be installed in several base directories
+    themes.icon("share/Custom/48x48/apps/elsewhere.png", 48);
+    themes.icon("share/Base/16x16/apps/inherited.png", 16);
+    themes.icon("share/Base/32x32/apps/inherited.png", 32);
+    themes.icon("share/hicolor/48x48/apps/fallback.png", 48);
+    themes.icon("pixmaps/unthemed.png", 24);
+    themes.pixmap("pixmaps/wide.png", 48, 32);
+
+    themes
+}
+
+fn relative<'a>(themes: &Themes, path: &'a Path) -> &'a Path {
+    path.strip_prefix(&themes.root).unwrap()
+}
+
+#[test]
+fn matches_size_and_scale() {
+    let themes = themes("matches");
+    let mut icons = themes.lookup();
+
+    let mut find = |name, size, scale| {
+        let path = icons.find("Custom", name, size, scale)?;
+        Some(relative(&themes, &path).to_path_buf())
+    };
+
+    assert_eq!(
+        find("custom", 48, 1).unwrap(),
+        Path::new("home/Custom/48x48/apps/custom.png")
+    );
+    assert_eq!(
+        find("custom", 48, 2).unwrap(),
+        Path::new("home/Custom/48x48@2/apps/custom.png")
+    );
+    assert_eq!(
+        find("elsewhere", 48, 1).unwrap(),
+        Path::new("share/Custom/48x48/apps/elsewhere.png")
+    );
+
+    // Inherited, from the closest size when none matches
+    assert_eq!(
+        find("inherited", 32, 1).unwrap(),
+        Path::new("share/Base/32x32/apps/inherited.png")
+    );
+    assert_eq!(
+        find("inherited", 20, 1).unwrap(),
+        Path::new("share/Base/16x16/apps/inherited.png")
+    );
+    assert_eq!(
+        find("inherited", 64, 1).unwrap(),
+        Path::new("share/Base/32x32/apps/inherited.png")
+    );
+
+    // Absurd sizes don't overflow, and still get the closest icon
+    assert!(find("custom", u32::MAX, u32::MAX).is_some());
+}
+
+#[test]
+fn falls_back_to_hicolor_and_pixmaps() {
+    let themes = themes("fallback");
+    let mut icons = themes.lookup();
+
+    let fallback = icons.find("Custom", "fallback", 48, 1).unwrap();
+    assert_eq!(
+        relative(&themes, &fallback),
+        Path::new("share/hicolor/48x48/apps/fallback.png")
+    );
+
+    let unthemed = icons.find("Custom", "unthemed", 48, 1).unwrap();
+    assert_eq!(
+        relative(&themes, &unthemed),
+        Path::new("pixmaps/unthemed.png")
+    );
+
+    // Missing themes still fall back
+    assert!(icons.find("Missing", "fallback", 48, 1).is_some());
+
+    // Inheriting in a cycle terminates
+    assert_eq!(icons.find("Custom", "missing", 48, 1), None);
+}
+
+#[test]
+#[cfg(all(feature = "texture_renderer", feature = "png"))]
+fn load() {
+    let themes = themes("load");
+    let mut icons = themes.lookup();
+
+    let icon = icons.load("Custom", "custom", 48, 2).unwrap().unwrap();
+    assert_eq!(icon.size(), (96, 96));
+
+    // Resized when no size matches
+    let icon = icons.load("Custom", "inherited", 24, 1).unwrap().unwrap();
+    assert_eq!(icon.size(), (24, 24));
+
+    // Fitted into the size, keeping its aspect ratio
+    let icon = icons.load("Custom", "wide", 24, 1).unwrap().unwrap();
+    assert_eq!(icon.size(), (24, 24));
+    let alpha = |x: usize, y: usize| icon.data()[(y * 24 + x) * 4 + 3];
+    assert_eq!(
+        [alpha(12, 3), alpha(12, 4), alpha(12, 19), alpha(12, 20)],
+        [0, 255, 255, 0]
+    );
+    assert_eq!([alpha(0, 12), alpha(23, 12)], [255, 255]);
+
+    assert!(icons.load("Custom", "missing", 48, 1).unwrap().is_none());
+}
+
+#[test]
+#[cfg(feature = "svg")]
+fn scalable() {
+    let themes = themes("scalable");
+    themes.write(
+        "home/Custom/scalable/apps/vector.svg",
+        br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+    <rect width="10" height="10" fill="red"/>
+</svg>"#,
+    );
+    let mut icons = themes.lookup();
+
+    // Rasterized at the size asked for, however large
+    let icon = icons.load("Custom", "vector", 64, 2).unwrap().unwrap();
+    assert_eq!(icon.size(), (128, 128));
+    assert_eq!(icon.data()[..4], [255, 0, 0, 255]);
+}
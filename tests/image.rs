@@ -0,0 +1,204 @@
+#![cfg(all(feature = "texture_renderer", feature = "png"))]
+
+use image::{ImageFormat, Rgba, RgbaImage};
+use moxui::image::{AnimatedImage, Image, ImageError};
+use std::io::Cursor;
+use std::time::Instant;
+
+/// A 3x2 PNG with a different color in every pixel.
+fn png() -> (RgbaImage, Vec<u8>) {
+    let image = RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8 * 80, y as u8 * 120, 40, 255]));
+
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+
+    (image, bytes)
+}
+
+#[test]
+fn from_bytes() {
+    let (expected, bytes) = png();
+
+    let image = Image::from_bytes(&bytes).unwrap();
+    assert_eq!(image.size(), (3, 2));
+    assert_eq!(image.data(), expected.as_raw().as_slice());
+
+    let read = Image::from_reader(Cursor::new(&bytes)).unwrap();
+    assert_eq!(read, image);
+}
+
+#[test]
+fn open_sniffs_format() {
+    let (expected, bytes) = png();
+
+    // The extension is wrong on purpose, the contents decide
+    let path = std::env::temp_dir().join(format!("moxui-{}-icon.jpg", std::process::id()));
+    std::fs::write(&path, &bytes).unwrap();
+    let image = Image::open(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(image.unwrap().data(), expected.as_raw().as_slice());
+}
+
+#[test]
+fn pad_centers() {
+    let (_, bytes) = png();
+    let image = Image::from_bytes(&bytes).unwrap();
+
+    // An odd difference puts the extra pixel right and below
+    let padded = image.clone().pad_rgba(6, 5, &[0, 0, 0, 0]);
+    assert_eq!(padded.size(), (6, 5));
+    let pixel = |x: usize, y: usize| &padded.data()[(y * 6 + x) * 4..][..4];
+    assert_eq!(pixel(0, 1), [0, 0, 0, 0]);
+    assert_eq!(pixel(1, 1), [0, 0, 40, 255]);
+    assert_eq!(pixel(3, 2), [160, 120, 40, 255]);
+    assert_eq!(pixel(4, 2), [0, 0, 0, 0]);
+    assert_eq!(pixel(1, 3), [0, 0, 0, 0]);
+
+    let padded = image.pad(5, 2, &[255, 255, 255]);
+    assert_eq!(padded.data()[..4], [255, 255, 255, 255]);
+    assert_eq!(padded.data()[4..8], [0, 0, 40, 255]);
+}
+
+#[test]
+fn unknown_format() {
+    let err = Image::from_bytes(b"not an image").unwrap_err();
+    assert!(matches!(err, ImageError::UnknownFormat));
+}
+
+#[test]
+#[cfg(not(feature = "gif"))]
+fn unsupported_format() {
+    let err = Image::from_bytes(b"GIF89a\x01\x00\x01\x00\x00\x00\x00;").unwrap_err();
+
+    assert!(matches!(err, ImageError::Unsupported(ImageFormat::Gif)));
+    assert!(err.to_string().contains("`gif` feature"));
+}
+
+#[test]
+fn still_image_has_one_frame() {
+    let (expected, bytes) = png();
+
+    let image = AnimatedImage::from_bytes(&bytes).unwrap();
+    assert!(!image.is_animated());
+    assert_eq!(image.frames()[0].image.data(), expected.as_raw().as_slice());
+    assert_eq!(image.next_frame_in(Instant::now()), None);
+}
+
+#[test]
+#[cfg(feature = "gif")]
+fn animated_gif() {
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame};
+    use std::time::Duration;
+
+    // The last delay of 0 is shown like browsers do
+    let frames = [
+        ([255, 0, 0, 255], 50),
+        ([0, 255, 0, 255], 100),
+        ([0, 0, 255, 255], 0),
+    ]
+    .map(|(color, delay)| {
+        Frame::from_parts(
+            RgbaImage::from_pixel(4, 4, Rgba(color)),
+            0,
+            0,
+            Delay::from_numer_denom_ms(delay, 1),
+        )
+    });
+
+    let mut bytes = Vec::new();
+    GifEncoder::new(&mut bytes).encode_frames(frames).unwrap();
+
+    let mut image = AnimatedImage::from_bytes(&bytes).unwrap();
+    let delays = image
+        .frames()
+        .iter()
+        .map(|frame| frame.delay.as_millis())
+        .collect::<Vec<_>>();
+    assert_eq!(delays, [50, 100, 100]);
+    assert_eq!(image.duration(), Duration::from_millis(250));
+    assert_eq!(image.frames()[1].image.data()[..4], [0, 255, 0, 255]);
+
+    let ms = Duration::from_millis;
+    let indices =
+        [0, 49, 50, 149, 150, 249, 250, 300].map(|elapsed| image.frame_index(ms(elapsed)));
+    assert_eq!(indices, [0, 0, 1, 1, 2, 2, 0, 1]);
+
+    // Paused until played, and paused again at the frame it was on
+    let start = Instant::now();
+    assert_eq!(image.frame(start + ms(60)), &image.frames()[0]);
+    image.play(start);
+    assert_eq!(image.frame(start + ms(60)), &image.frames()[1]);
+    assert_eq!(image.next_frame_in(start + ms(60)), Some(ms(90)));
+
+    image.pause(start + ms(60));
+    assert_eq!(image.frame(start + ms(1000)), &image.frames()[1]);
+    assert_eq!(image.next_frame_in(start + ms(1000)), None);
+
+    image.play(start + ms(1000));
+    assert_eq!(image.elapsed(start + ms(1100)), ms(160));
+    image.seek(Duration::ZERO, start + ms(1100));
+    assert_eq!(image.frame(start + ms(1100)), &image.frames()[0]);
+}
+
+/// Red circle in a 10x10 view box, with a blue square in its center.
+#[cfg(feature = "svg")]
+const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+    <circle cx="5" cy="5" r="5" fill="red"/>
+    <rect x="4" y="4" width="2" height="2" fill="blue"/>
+</svg>"#;
+
+#[test]
+#[cfg(feature = "svg")]
+fn from_svg() {
+    // Fitted into the height and centered horizontally
+    let image = Image::from_svg(SVG.as_bytes(), 40, 20).unwrap();
+    assert_eq!(image.size(), (40, 20));
+
+    let pixel = |x: u32, y: u32| {
+        let i = ((y * image.width() + x) * 4) as usize;
+        <[u8; 4]>::try_from(&image.data()[i..i + 4]).unwrap()
+    };
+    assert_eq!(pixel(5, 10), [0, 0, 0, 0]);
+    assert_eq!(pixel(13, 10), [255, 0, 0, 255]);
+    assert_eq!(pixel(20, 10), [0, 0, 255, 255]);
+    assert_eq!(pixel(35, 10), [0, 0, 0, 0]);
+
+    assert!(matches!(
+        Image::from_svg(b"<svg", 16, 16),
+        Err(ImageError::Svg(_))
+    ));
+}
+
+#[test]
+#[cfg(feature = "svg")]
+fn svg_cache() {
+    use moxui::image::SvgCache;
+
+    let path = std::env::temp_dir().join(format!("moxui-{}-icon.svg", std::process::id()));
+    std::fs::write(&path, SVG).unwrap();
+
+    let mut svgs = SvgCache::default();
+    let small = svgs.get(&path, 16, 1.).unwrap().clone();
+    let large = svgs.get(&path, 16, 2.).unwrap().clone();
+    assert_eq!(small.size(), (16, 16));
+    assert_eq!(large.size(), (32, 32));
+
+    // Rasterized again rather than upscaled, which would blur the edges of
+    // the square
+    assert_ne!(large, small.clone().resize_stretch(32, 32).unwrap());
+    let edge = ((16 * 32 + 13) * 4) as usize;
+    assert_eq!(large.data()[edge..edge + 4], [0, 0, 255, 255]);
+
+    // Cached rasters outlive changes on disk until they're removed
+    std::fs::write(&path, SVG.replace("blue", "lime")).unwrap();
+    assert_eq!(svgs.get(&path, 16, 1.).unwrap(), &small);
+    svgs.remove(&path);
+    let changed = svgs.get(&path, 16, 1.).unwrap().clone();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_ne!(changed, small);
+}